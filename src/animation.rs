@@ -0,0 +1,115 @@
+use nalgebra::{Matrix4, Rotation3, Unit, Vector3};
+use std::f32;
+
+/// Spins the scene at a constant angular velocity, or swings it back and forth
+#[derive(Clone, PartialEq, Debug)]
+pub struct Turntable {
+    pub orientation: Vector3<f32>, // Euler angles about X, Y and Z the spin starts from, in radians
+    pub velocity: Vector3<f32>,    // The spin axis, as long as the spin speed in radians per second
+    pub oscillation: Option<f32>,  // Swings this far either side and back instead of spinning
+    pub time: f32,                 // In seconds
+}
+
+impl Default for Turntable {
+    fn default() -> Self {
+        Self {
+            orientation: Vector3::zeros(),
+            velocity: Vector3::y(), // 1 rad/s around the vertical axis
+            oscillation: None,
+            time: 0.0,
+        }
+    }
+}
+
+impl Turntable {
+    pub fn advance(&mut self, dt: f32) {
+        self.time += dt;
+    }
+    /// How far the scene has turned around the spin axis, in radians
+    pub fn angle(&self) -> f32 {
+        let travelled = self.velocity.norm() * self.time;
+        match self.oscillation {
+            Some(amplitude) if amplitude > 0.0 => {
+                // A triangle wave: up to the amplitude, down past zero to minus the amplitude, back up
+                let phase = travelled.rem_euclid(4.0 * amplitude);
+                if phase < amplitude {
+                    phase
+                } else if phase < 3.0 * amplitude {
+                    2.0 * amplitude - phase
+                } else {
+                    phase - 4.0 * amplitude
+                }
+            }
+            _ => travelled,
+        }
+    }
+    /// How long it takes to come back to the start, `None` when standing still
+    pub fn period(&self) -> Option<f32> {
+        let speed = self.velocity.norm();
+        if speed <= 0.0 {
+            return None;
+        }
+        match self.oscillation {
+            Some(amplitude) if amplitude > 0.0 => Some(4.0 * amplitude / speed),
+            _ => Some(2.0 * f32::consts::PI / speed),
+        }
+    }
+    /// The model transform at the current time
+    pub fn transform(&self) -> Matrix4<f32> {
+        let (x, y, z) = (self.orientation.x, self.orientation.y, self.orientation.z);
+        let start = Rotation3::from_euler_angles(x, y, z);
+        let spin = match Unit::try_new(self.velocity, f32::EPSILON) {
+            Some(axis) => Rotation3::from_axis_angle(&axis, self.angle()),
+            None => Rotation3::identity(),
+        };
+        (spin * start).to_homogeneous()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_spin() {
+        let mut turntable = Turntable {
+            velocity: Vector3::new(0.0, 0.0, 2.0),
+            ..Turntable::default()
+        };
+        assert_eq!(turntable.period(), Some(PI));
+        turntable.advance(PI / 4.0);
+        let x = turntable.transform().transform_vector(&Vector3::x());
+        assert!((x - Vector3::y()).norm() < 1e-5); // A quarter turn around Z
+    }
+
+    #[test]
+    fn test_oscillation() {
+        let mut turntable = Turntable {
+            oscillation: Some(0.5),
+            ..Turntable::default()
+        };
+        assert_eq!(turntable.period(), Some(2.0));
+        let mut angles = vec![];
+        for _ in 0..8 {
+            turntable.advance(0.25);
+            angles.push(turntable.angle());
+        }
+        let expected = [0.25, 0.5, 0.25, 0.0, -0.25, -0.5, -0.25, 0.0];
+        for (angle, expected) in angles.iter().zip(expected.iter()) {
+            assert!((angle - expected).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_standing_still() {
+        let turntable = Turntable {
+            orientation: Vector3::new(0.0, PI, 0.0),
+            velocity: Vector3::zeros(),
+            ..Turntable::default()
+        };
+        assert_eq!(turntable.period(), None);
+        let x = turntable.transform().transform_vector(&Vector3::x());
+        assert!((x + Vector3::x()).norm() < 1e-5);
+    }
+}
@@ -0,0 +1,221 @@
+use crate::geometry::SimpleMesh;
+use nalgebra::{
+    Matrix4, Orthographic3, Perspective3, Point3, Rotation3, Unit, UnitQuaternion, Vector2, Vector3,
+};
+use std::f32;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+}
+
+/// A look-at camera. `fov` is the vertical field of view in radians, orthographic cameras use it
+/// to size their view volume so switching projections keeps the target framed the same way.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Camera {
+    pub position: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    pub fov: f32,
+    pub near: f32,
+    pub far: f32,
+    pub projection: Projection,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: Point3::new(0.0, 0.0, 3.0),
+            target: Point3::origin(),
+            up: Vector3::y(),
+            fov: f32::consts::FRAC_PI_4,
+            near: 0.1,
+            far: 100.0,
+            projection: Projection::Perspective,
+        }
+    }
+}
+
+impl Camera {
+    /// Places a camera on the +Z axis, far enough back for every mesh to fit in view
+    pub fn fit(meshes: &[SimpleMesh]) -> Camera {
+        let mut camera = Camera::default();
+        camera.frame(meshes);
+        camera
+    }
+    /// Moves the camera onto the +Z axis, looking at the origin from just far enough back for every
+    /// mesh to fit in its field of view
+    pub fn frame(&mut self, meshes: &[SimpleMesh]) {
+        let mut radius: f32 = 0.0; // The scene's bounding sphere, centered around the origin
+        for mesh in meshes {
+            let (min, max) = (mesh.bounding_box.min, mesh.bounding_box.max);
+            for corner in 0..8 {
+                let pick = |axis: usize| {
+                    if corner & (1 << axis) == 0 {
+                        min[axis]
+                    } else {
+                        max[axis]
+                    }
+                };
+                radius = radius.max(Vector3::new(pick(0), pick(1), pick(2)).norm());
+            }
+        }
+        if radius <= 0.0 {
+            radius = 1.0;
+        }
+        let distance = radius / (self.fov / 2.0).sin();
+        self.position = Point3::new(0.0, 0.0, distance);
+        self.target = Point3::origin();
+        self.near = radius * 0.01;
+        self.far = distance + radius * 4.0;
+    }
+    /// Swings the camera around its target, `yaw` radians around the up axis and `pitch` radians
+    /// over it. It stops short of looking straight along the up axis.
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        let up = self.up.normalize();
+        let offset = Rotation3::from_axis_angle(&Unit::new_unchecked(up), yaw)
+            * (self.position - self.target);
+        let elevation = offset.angle(&up); // From the up axis
+        let rise = elevation - (elevation - pitch).clamp(0.01, f32::consts::PI - 0.01);
+        let offset = match Unit::try_new(up.cross(&offset), f32::EPSILON) {
+            Some(right) => Rotation3::from_axis_angle(&right, -rise) * offset,
+            None => offset,
+        };
+        self.position = self.target + offset;
+    }
+    /// Turns the scene as if dragging a ball around the target from `from` to `to`. Both are screen
+    /// positions with the ball's center at the origin, its edge at 1 and y pointing up.
+    pub fn arcball(&mut self, from: Vector2<f32>, to: Vector2<f32>) {
+        let onto_ball = |point: Vector2<f32>| {
+            let squared = point.norm_squared();
+            if squared <= 1.0 {
+                Vector3::new(point.x, point.y, (1.0 - squared).sqrt())
+            } else {
+                Vector3::new(point.x, point.y, 0.0) / squared.sqrt()
+            }
+        };
+        let turn = match UnitQuaternion::rotation_between(&onto_ball(from), &onto_ball(to)) {
+            Some(turn) => turn,
+            None => return,
+        };
+        // The ball turns in view space, the camera turns the other way around the target in world space
+        let to_world = self.view().try_inverse().unwrap_or_else(Matrix4::identity);
+        let turn = match turn.axis() {
+            Some(axis) => UnitQuaternion::from_axis_angle(
+                &Unit::new_normalize(to_world.transform_vector(&axis)),
+                -turn.angle(),
+            ),
+            None => return,
+        };
+        self.position = self.target + turn * (self.position - self.target);
+        self.up = turn * self.up;
+    }
+    /// Moves the camera towards its target by `factor` of the distance, less than 1 zooms in.
+    /// The far plane follows it, so the scene's depth stays in view.
+    pub fn zoom(&mut self, factor: f32) {
+        let offset = self.position - self.target;
+        let distance = offset.norm();
+        let zoomed = (distance * factor).max(self.near * 2.0);
+        self.position = self.target + offset * (zoomed / distance);
+        self.far += zoomed - distance;
+    }
+    /// Slides the camera and its target sideways by `right` and upwards by `up`, both in distances
+    /// to the target
+    pub fn pan(&mut self, right: f32, up: f32) {
+        let forward = self.target - self.position;
+        let distance = forward.norm();
+        let side = forward.cross(&self.up).normalize();
+        let above = side.cross(&forward).normalize();
+        let shift = (side * right + above * up) * distance;
+        self.position += shift;
+        self.target += shift;
+    }
+    pub fn view(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(&self.position, &self.target, &self.up)
+    }
+    /// The projection matrix for a viewport `aspect` times wider than it is tall
+    pub fn projection(&self, aspect: f32) -> Matrix4<f32> {
+        match self.projection {
+            Projection::Perspective => {
+                Perspective3::new(aspect, self.fov, self.near, self.far).to_homogeneous()
+            }
+            Projection::Orthographic => {
+                let top = (self.position - self.target).norm() * (self.fov / 2.0).tan();
+                let right = top * aspect;
+                Orthographic3::new(-right, right, -top, top, self.near, self.far).to_homogeneous()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector4;
+
+    fn to_ndc(camera: &Camera, point: Vector4<f32>) -> Vector4<f32> {
+        let clip = camera.projection(1.0) * camera.view() * point;
+        clip / clip.w
+    }
+
+    #[test]
+    fn test_target_is_centered() {
+        let camera = Camera::default();
+        let ndc = to_ndc(&camera, Vector4::new(0.0, 0.0, 0.0, 1.0));
+        assert!(ndc.x.abs() < 1e-6 && ndc.y.abs() < 1e-6);
+        assert!(ndc.z > -1.0 && ndc.z < 1.0);
+    }
+
+    #[test]
+    fn test_orbit() {
+        let mut camera = Camera::default();
+        camera.orbit(f32::consts::FRAC_PI_2, 0.0);
+        assert!((camera.position - Point3::new(3.0, 0.0, 0.0)).norm() < 1e-5);
+        camera.orbit(0.0, 10.0); // Can't go over the top
+        let offset = camera.position - camera.target;
+        assert!((offset.norm() - 3.0).abs() < 1e-5);
+        assert!(offset.y > 2.9 && offset.angle(&Vector3::y()) > 0.0);
+    }
+
+    #[test]
+    fn test_arcball() {
+        // Dragging from the center to the right edge turns the camera a quarter to the left
+        let mut camera = Camera::default();
+        camera.arcball(Vector2::zeros(), Vector2::new(1.0, 0.0));
+        assert!((camera.position - Point3::new(-3.0, 0.0, 0.0)).norm() < 1e-4);
+        assert!((camera.up - Vector3::y()).norm() < 1e-5);
+        // Dragging down then up again comes back
+        camera.arcball(Vector2::zeros(), Vector2::new(0.0, -0.5));
+        camera.arcball(Vector2::new(0.0, -0.5), Vector2::zeros());
+        assert!((camera.position - Point3::new(-3.0, 0.0, 0.0)).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_zoom_and_pan() {
+        let mut camera = Camera::default();
+        camera.zoom(0.5);
+        assert!((camera.position.z - 1.5).abs() < 1e-5);
+        assert!((camera.far - 98.5).abs() < 1e-3);
+        camera.pan(1.0, 0.0);
+        assert!((camera.position - Point3::new(1.5, 0.0, 1.5)).norm() < 1e-5);
+        assert!((camera.target - Point3::new(1.5, 0.0, 0.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn test_perspective_shrinks_with_distance() {
+        let camera = Camera::default();
+        let near = to_ndc(&camera, Vector4::new(0.5, 0.0, 1.0, 1.0));
+        let far = to_ndc(&camera, Vector4::new(0.5, 0.0, -1.0, 1.0));
+        assert!(near.x > far.x);
+        assert!(near.z < far.z);
+
+        let camera = Camera {
+            projection: Projection::Orthographic,
+            ..Camera::default()
+        };
+        let near = to_ndc(&camera, Vector4::new(0.5, 0.0, 1.0, 1.0));
+        let far = to_ndc(&camera, Vector4::new(0.5, 0.0, -1.0, 1.0));
+        assert!((near.x - far.x).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,149 @@
+use nalgebra::{Vector3, Vector4};
+
+/// A vertex of a clipped triangle. `barycentric` locates it within the triangle it was clipped
+/// from, so any attribute of the original vertices can be interpolated onto it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ClipVertex {
+    pub position: Vector4<f32>,
+    pub barycentric: Vector3<f32>,
+}
+
+impl ClipVertex {
+    fn lerp(&self, other: &ClipVertex, t: f32) -> ClipVertex {
+        ClipVertex {
+            position: self.position.lerp(&other.position, t),
+            barycentric: self.barycentric.lerp(&other.barycentric, t),
+        }
+    }
+}
+
+// Signed distances to the six frustum planes, -w <= x, y, z <= w. Positive is inside
+const PLANES: [fn(&Vector4<f32>) -> f32; 6] = [
+    |v| v.w + v.x,
+    |v| v.w - v.x,
+    |v| v.w + v.y,
+    |v| v.w - v.y,
+    |v| v.w + v.z,
+    |v| v.w - v.z,
+];
+
+/// Clips a clip space triangle against the view frustum (Sutherland–Hodgman), returning the visible
+/// part as a fan of triangles. Triangles that are entirely visible are returned as they are.
+pub fn clip_triangle(triangle: [Vector4<f32>; 3]) -> Vec<[ClipVertex; 3]> {
+    let mut polygon: Vec<ClipVertex> = triangle
+        .iter()
+        .zip(&[Vector3::x(), Vector3::y(), Vector3::z()])
+        .map(|(position, barycentric)| ClipVertex {
+            position: *position,
+            barycentric: *barycentric,
+        })
+        .collect();
+
+    for plane in PLANES.iter() {
+        if polygon.iter().all(|vertex| plane(&vertex.position) >= 0.0) {
+            continue; // Nothing to cut off
+        }
+        let mut clipped = Vec::with_capacity(polygon.len() + 1);
+        for (i, current) in polygon.iter().enumerate() {
+            let next = &polygon[(i + 1) % polygon.len()];
+            let (d0, d1) = (plane(&current.position), plane(&next.position));
+            if d0 >= 0.0 {
+                clipped.push(*current);
+            }
+            if (d0 >= 0.0) != (d1 >= 0.0) {
+                clipped.push(current.lerp(next, d0 / (d0 - d1)));
+            }
+        }
+        polygon = clipped;
+        if polygon.len() < 3 {
+            return vec![];
+        }
+    }
+
+    (1..polygon.len() - 1)
+        .map(|i| [polygon[0], polygon[i], polygon[i + 1]])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inside(vertex: &ClipVertex) -> bool {
+        PLANES.iter().all(|plane| plane(&vertex.position) >= -1e-5)
+    }
+
+    #[test]
+    fn test_visible_triangle_is_untouched() {
+        let triangle = [
+            Vector4::new(-0.5, -0.5, 0.0, 1.0),
+            Vector4::new(0.5, -0.5, 0.0, 1.0),
+            Vector4::new(0.0, 0.5, 0.0, 1.0),
+        ];
+        let clipped = clip_triangle(triangle);
+        assert_eq!(clipped.len(), 1);
+        for (vertex, position) in clipped[0].iter().zip(&triangle) {
+            assert_eq!(vertex.position, *position);
+        }
+        assert_eq!(clipped[0][1].barycentric, Vector3::y());
+    }
+
+    #[test]
+    fn test_invisible_triangle_is_dropped() {
+        let triangle = [
+            Vector4::new(2.0, 2.0, 0.0, 1.0),
+            Vector4::new(3.0, 2.0, 0.0, 1.0),
+            Vector4::new(2.0, 3.0, 0.0, 1.0),
+        ];
+        assert!(clip_triangle(triangle).is_empty());
+    }
+
+    #[test]
+    fn test_one_corner_outside() {
+        // The corner past the right plane is cut off, leaving a quad
+        let triangle = [
+            Vector4::new(0.0, -0.5, 0.0, 1.0),
+            Vector4::new(2.0, 0.0, 0.0, 1.0),
+            Vector4::new(0.0, 0.5, 0.0, 1.0),
+        ];
+        let clipped = clip_triangle(triangle);
+        assert_eq!(clipped.len(), 2);
+        for vertex in clipped.iter().flatten() {
+            assert!(inside(vertex));
+            assert!((vertex.barycentric.sum() - 1.0).abs() < 1e-5);
+            // Interpolating the original positions must give back the clipped position
+            let position = triangle[0] * vertex.barycentric.x
+                + triangle[1] * vertex.barycentric.y
+                + triangle[2] * vertex.barycentric.z;
+            assert!((position - vertex.position).norm() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_two_corners_outside() {
+        let triangle = [
+            Vector4::new(0.0, 0.0, 0.0, 1.0),
+            Vector4::new(0.0, 3.0, 0.0, 1.0),
+            Vector4::new(3.0, 0.0, 0.0, 1.0),
+        ];
+        let clipped = clip_triangle(triangle);
+        assert_eq!(clipped.len(), 2); // Only the square between the origin and the top right corner is left
+        assert!(clipped.iter().flatten().all(inside));
+    }
+
+    #[test]
+    fn test_behind_the_camera() {
+        // One vertex is behind the eye (w < 0) and must never reach the perspective divide
+        let triangle = [
+            Vector4::new(0.0, 0.0, 0.5, 1.0),
+            Vector4::new(0.5, 0.0, 0.5, 1.0),
+            Vector4::new(0.0, 0.5, -2.0, -1.0),
+        ];
+        let clipped = clip_triangle(triangle);
+        assert!(!clipped.is_empty());
+        for vertex in clipped.iter().flatten() {
+            assert!(vertex.position.w > 0.0);
+            assert!(inside(vertex));
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::env;
+
+/// How many colors the terminal can show
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorDepth {
+    TrueColor, // 24 bit RGB
+    Ansi256,   // The xterm palette: 16 system colors, a 6×6×6 cube and 24 grays
+    Ansi16,    // The 8 standard colors and their bright versions
+    Mono,      // No colors at all, only glyphs
+}
+
+/// The xterm values of the 16 standard colors, in SGR order
+pub const ANSI_16: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (128, 0, 0),
+    (0, 128, 0),
+    (128, 128, 0),
+    (0, 0, 128),
+    (128, 0, 128),
+    (0, 128, 128),
+    (192, 192, 192),
+    (128, 128, 128),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (0, 0, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+const BAYER_4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+impl ColorDepth {
+    /// Guesses the terminal's color depth from the `COLORTERM` and `TERM` environment variables
+    pub fn detect() -> ColorDepth {
+        let colorterm = env::var("COLORTERM").ok();
+        let term = env::var("TERM").ok();
+        if term.is_none() && cfg!(windows) {
+            return ColorDepth::TrueColor; // Windows consoles don't set TERM, but have true color
+        }
+        ColorDepth::from_env(colorterm.as_deref(), term.as_deref())
+    }
+    pub fn from_env(colorterm: Option<&str>, term: Option<&str>) -> ColorDepth {
+        if let Some("truecolor") | Some("24bit") = colorterm {
+            return ColorDepth::TrueColor;
+        }
+        match term {
+            None | Some("") | Some("dumb") => ColorDepth::Mono,
+            Some(term) if term.contains("direct") || term.contains("truecolor") => {
+                ColorDepth::TrueColor
+            }
+            Some(term) if term.contains("256color") => ColorDepth::Ansi256,
+            Some(_) => ColorDepth::Ansi16,
+        }
+    }
+    /// Roughly how far apart the palette's colors are, the range dithering spreads colors over
+    fn spread(self) -> f32 {
+        match self {
+            ColorDepth::Ansi256 => 40.0,
+            ColorDepth::Ansi16 => 128.0,
+            _ => 0.0,
+        }
+    }
+    /// Nudges `color` by an ordered (Bayer) dither for the cell at `x`, `y`, so neighbouring cells
+    /// quantize to a mix of the nearest palette colors
+    pub fn dither(self, color: (u8, u8, u8), x: usize, y: usize) -> (u8, u8, u8) {
+        let threshold = (BAYER_4[y % 4][x % 4] as f32 + 0.5) / 16.0 - 0.5;
+        let offset = threshold * self.spread();
+        let channel = |value: u8| (value as f32 + offset).clamp(0.0, 255.0) as u8;
+        (channel(color.0), channel(color.1), channel(color.2))
+    }
+}
+
+/// What's drawn behind the cells that don't set their own background
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Background {
+    Solid((u8, u8, u8)),
+    Gradient((u8, u8, u8), (u8, u8, u8)), // From the top row to the bottom one
+    Transparent,                          // Whatever the terminal or page has behind it
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Solid((25, 25, 25))
+    }
+}
+
+impl Background {
+    /// The background's color on `row`, out of `rows`
+    pub fn color(self, row: usize, rows: usize) -> Option<(u8, u8, u8)> {
+        match self {
+            Background::Solid(color) => Some(color),
+            Background::Gradient(top, bottom) => {
+                let t = row as f32 / (rows.max(2) - 1) as f32;
+                let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+                Some((
+                    mix(top.0, bottom.0),
+                    mix(top.1, bottom.1),
+                    mix(top.2, bottom.2),
+                ))
+            }
+            Background::Transparent => None,
+        }
+    }
+}
+
+/// The `index`th of a sequence of distinct colors, for telling objects apart. Hues step by the
+/// golden ratio, so however many there are, neighbours are far apart.
+pub fn palette(index: usize) -> (u8, u8, u8) {
+    let hue = (index as f32 * 0.618_034).fract() * 6.0;
+    let (value, saturation) = (0.95, 0.6);
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let channel = |c: f32| ((c + value - chroma) * 255.0).round() as u8;
+    (channel(r), channel(g), channel(b))
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> i32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+/// The closest color of the xterm 256 color palette's cube and grays
+pub fn ansi_256(color: (u8, u8, u8)) -> u8 {
+    let level = |value: u8| {
+        (0..6)
+            .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - value as i32).abs())
+            .unwrap_or(0)
+    };
+    let (r, g, b) = (level(color.0), level(color.1), level(color.2));
+    let cube = (CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]);
+    let average = (color.0 as u32 + color.1 as u32 + color.2 as u32) / 3;
+    let gray = ((average.max(8) - 3) / 10).min(23) as u8; // Rounded to the nearest of 8, 18, ..., 238
+    let gray_value = 8 + gray * 10;
+    if distance(color, (gray_value, gray_value, gray_value)) < distance(color, cube) {
+        232 + gray
+    } else {
+        16 + 36 * r as u8 + 6 * g as u8 + b as u8
+    }
+}
+
+/// The closest of the 16 standard colors, as its index into `ANSI_16`
+pub fn ansi_16(color: (u8, u8, u8)) -> u8 {
+    (0..16)
+        .min_by_key(|&i| distance(color, ANSI_16[i as usize]))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            ColorDepth::from_env(Some("truecolor"), Some("xterm")),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(
+            ColorDepth::from_env(None, Some("screen-256color")),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(
+            ColorDepth::from_env(None, Some("linux")),
+            ColorDepth::Ansi16
+        );
+        assert_eq!(ColorDepth::from_env(None, Some("dumb")), ColorDepth::Mono);
+        assert_eq!(ColorDepth::from_env(None, None), ColorDepth::Mono);
+    }
+
+    #[test]
+    fn test_palette() {
+        assert_eq!(palette(0), (242, 97, 97));
+        let colors = (0..8).map(palette).collect::<Vec<_>>();
+        for (i, a) in colors.iter().enumerate() {
+            for b in &colors[i + 1..] {
+                assert!(distance(*a, *b) > 2000);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ansi_256() {
+        assert_eq!(ansi_256((255, 0, 0)), 196);
+        assert_eq!(ansi_256((0, 0, 0)), 16);
+        assert_eq!(ansi_256((255, 255, 255)), 231);
+        assert_eq!(ansi_256((25, 25, 25)), 234);
+    }
+
+    #[test]
+    fn test_ansi_16() {
+        assert_eq!(ansi_16((250, 10, 10)), 9);
+        assert_eq!(ansi_16((120, 0, 0)), 1);
+        assert_eq!(ansi_16((25, 25, 25)), 0);
+    }
+
+    #[test]
+    fn test_background() {
+        let gradient = Background::Gradient((0, 0, 0), (200, 100, 50));
+        assert_eq!(gradient.color(0, 5), Some((0, 0, 0)));
+        assert_eq!(gradient.color(2, 5), Some((100, 50, 25)));
+        assert_eq!(gradient.color(4, 5), Some((200, 100, 50)));
+        assert_eq!(Background::Transparent.color(0, 5), None);
+    }
+
+    #[test]
+    fn test_dither() {
+        // A color halfway between two palette colors quantizes to both across a block of cells
+        let middle = (64, 64, 64);
+        let colors: Vec<u8> = (0..16)
+            .map(|i| ansi_16(ColorDepth::Ansi16.dither(middle, i % 4, i / 4)))
+            .collect();
+        assert!(colors.contains(&0) && colors.contains(&8));
+        assert_eq!(ColorDepth::TrueColor.dither(middle, 1, 2), middle);
+    }
+}
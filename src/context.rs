@@ -1,142 +1,401 @@
-use crate::geometry::SimpleMesh;
-use crossterm::{
-    cursor,
-    style::{style, Color, PrintStyledContent},
-    terminal, QueueableCommand,
-};
+use crate::camera::Camera;
+use crate::color::{ansi_16, ansi_256, Background, ColorDepth};
+use crate::lighting::Light;
+use crate::rasterizer::{Culling, Shading, Winding};
+use crossterm::{cursor, QueueableCommand};
 use nalgebra::Matrix4;
 use std::error::Error;
 use std::f32;
-use std::io::stdout;
+use std::io::Write;
+
+/// One character on screen
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Cell {
+    pub glyph: char,
+    pub foreground: (u8, u8, u8),
+    pub background: Option<(u8, u8, u8)>, // `None` leaves the context's own background
+}
+
+impl Cell {
+    pub fn blank() -> Cell {
+        Cell {
+            glyph: ' ',
+            foreground: (0, 0, 0),
+            background: None,
+        }
+    }
+}
+
+/// How pixels are packed into cells
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PixelMode {
+    Glyph,     // Every pixel is a glyph, doubled across two cells to make it square
+    HalfBlock, // Every cell holds two pixels stacked as ▀ and ▄, in their own colors
+    Braille,   // Every cell holds 2×4 pixels as Braille dots, in their average color
+    Bitmap,    // Every cell is one pixel in its lit color, for image files rather than terminals
+}
+
+impl PixelMode {
+    /// The size of the pixel grid drawn into `width` by `height` cells
+    pub fn resolution(self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            PixelMode::Glyph => (width / 2, height),
+            PixelMode::HalfBlock => (width, height * 2),
+            PixelMode::Braille => (width * 2, height * 4),
+            PixelMode::Bitmap => (width, height),
+        }
+    }
+}
+
+/// Counts the bytes written through it
+struct Counter<'a, W: Write> {
+    out: &'a mut W,
+    bytes: usize,
+}
+
+impl<'a, W: Write> Write for Counter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.out.write(buf)?;
+        self.bytes += written;
+        Ok(written)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
 
 pub struct Context {
-    pub utransform: Matrix4<f32>,
+    pub utransform: Matrix4<f32>, // Projection * view, takes world space to clip space
+    pub view: Matrix4<f32>,
     pub width: usize,
     pub height: usize,
-    pub frame_buffer: Vec<(char, (u8, u8, u8))>,
-    pub z_buffer: Vec<f32>,
+    pub mode: PixelMode,
+    pub pixels: Vec<Option<Cell>>, // What was drawn on every pixel, packed into the frame buffer's cells by `resolve`
+    pub frame_buffer: Vec<Cell>,
+    pub previous: Vec<Cell>,  // The last frame flushed to the terminal
+    pub bytes_flushed: usize, // How many bytes the last flush wrote
+    pub z_buffer: Vec<f32>,   // One depth per pixel
     pub image: bool,
+    pub color_depth: ColorDepth, // What colors are flushed to terminals as
+    pub dither: bool,            // Whether colors are dithered when quantizing them to a palette
+    pub background: Background,  // Behind the cells that don't have a background of their own
+    pub culling: Culling,
+    pub winding: Winding, // Which way front faces wind
+    pub shading: Shading,
+    pub lights: Vec<Light>, // In view space
 }
 
 impl Context {
     pub fn blank(image: bool) -> Context {
         //TODO: Make this a constant struct
         Context {
-            utransform: Matrix4::new(
-                1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
-            ),
+            utransform: Matrix4::identity(),
+            view: Matrix4::identity(),
             width: 0,
             height: 0,
+            mode: PixelMode::Glyph,
+            pixels: vec![],
             frame_buffer: vec![],
+            previous: vec![],
+            bytes_flushed: 0,
             z_buffer: vec![],
             image,
+            color_depth: ColorDepth::TrueColor,
+            dither: false,
+            background: Background::default(),
+            culling: Culling::None,
+            winding: Winding::CounterClockwise,
+            shading: Shading::Phong,
+            lights: vec![Light::headlight()],
         }
     }
+    /// The size of the pixel grid
+    pub fn resolution(&self) -> (usize, usize) {
+        self.mode.resolution(self.width, self.height)
+    }
     pub fn clear(&mut self) {
-        self.frame_buffer = vec![
-            (' ', (0, 0, 0));
-            if self.image {
-                self.width * self.height + self.height
-            } else {
-                self.width * self.height
-            } as usize
-        ];
-        self.z_buffer = vec![f32::MAX; self.width * self.height as usize]; //f32::MAX is written to the z-buffer as an infinite back-wall to render with
+        let (width, height) = self.resolution();
+        self.pixels = vec![None; width * height];
+        self.frame_buffer = vec![Cell::blank(); self.width * self.height];
+        self.z_buffer = vec![f32::MAX; width * height]; //f32::MAX is written to the z-buffer as an infinite back-wall to render with
+    }
+    /// Packs the pixels into the frame buffer's cells
+    pub fn resolve(&mut self) {
+        let (width, height) = self.resolution();
+        let mut colors = vec![(0, 0, 0, 0); self.frame_buffer.len()]; // Braille cells' color sums and dot counts
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = match self.pixels[y * width + x] {
+                    Some(pixel) => pixel,
+                    None => continue,
+                };
+                match self.mode {
+                    PixelMode::Bitmap => self.frame_buffer[y * self.width + x] = pixel,
+                    PixelMode::Glyph => {
+                        let id = y * self.width + x * 2;
+                        self.frame_buffer[id] = pixel;
+                        self.frame_buffer[id + 1] = pixel;
+                    }
+                    PixelMode::HalfBlock => {
+                        let cell = &mut self.frame_buffer[y / 2 * self.width + x];
+                        if y % 2 == 0 {
+                            *cell = Cell {
+                                glyph: '▀',
+                                foreground: pixel.foreground,
+                                background: None,
+                            };
+                        } else if cell.glyph == '▀' {
+                            cell.background = Some(pixel.foreground); // Under a top pixel
+                        } else {
+                            *cell = Cell {
+                                glyph: '▄',
+                                foreground: pixel.foreground,
+                                background: None,
+                            };
+                        }
+                    }
+                    PixelMode::Braille => {
+                        let id = y / 4 * self.width + x / 2;
+                        let dot = match (x % 2, y % 4) {
+                            (0, 3) => 0x40,
+                            (1, 3) => 0x80,
+                            (column, row) => 1 << (column * 3 + row),
+                        };
+                        let pattern = (self.frame_buffer[id].glyph as u32).saturating_sub(0x2800);
+                        let glyph = char::from_u32(0x2800 | (pattern & 0xFF) | dot).unwrap_or(' ');
+                        let color = &mut colors[id];
+                        *color = (
+                            color.0 + pixel.foreground.0 as u32,
+                            color.1 + pixel.foreground.1 as u32,
+                            color.2 + pixel.foreground.2 as u32,
+                            color.3 + 1,
+                        );
+                        let count = color.3;
+                        self.frame_buffer[id] = Cell {
+                            glyph,
+                            foreground: (
+                                (color.0 / count) as u8,
+                                (color.1 / count) as u8,
+                                (color.2 / count) as u8,
+                            ),
+                            background: None,
+                        };
+                    }
+                }
+            }
+        }
     }
     pub fn camera(&mut self, proj: Matrix4<f32>, view: Matrix4<f32>) -> &Matrix4<f32> {
         self.utransform = proj * view;
+        self.view = view;
         &self.utransform
     }
-    pub fn flush(&self, color: bool, webify: bool) -> Result<(), Box<dyn Error>> {
-        let mut stdout = stdout();
-
-        if !self.image {
-            stdout.queue(cursor::MoveTo(0, 0))?;
+    /// What's behind the cell at `id`: its own background, or else the context's
+    pub fn background_at(&self, id: usize) -> Option<(u8, u8, u8)> {
+        self.frame_buffer[id]
+            .background
+            .or_else(|| self.background.color(id / self.width.max(1), self.height))
+    }
+    /// The escape sequence setting the colors of the cell at `id`, `None` on monochrome terminals
+    fn escape(&self, cell: &Cell, id: usize) -> Option<String> {
+        let mut background = self.background_at(id);
+        let mut foreground = cell.foreground;
+        if self.dither {
+            let (x, y) = (id % self.width.max(1), id / self.width.max(1));
+            foreground = self.color_depth.dither(foreground, x, y);
+            background = background.map(|color| self.color_depth.dither(color, x, y));
         }
+        let f = foreground;
+        let (foreground, background) = match self.color_depth {
+            ColorDepth::TrueColor => (
+                format!("38;2;{};{};{}", f.0, f.1, f.2),
+                background.map(|b| format!("48;2;{};{};{}", b.0, b.1, b.2)),
+            ),
+            ColorDepth::Ansi256 => (
+                format!("38;5;{}", ansi_256(f)),
+                background.map(|b| format!("48;5;{}", ansi_256(b))),
+            ),
+            ColorDepth::Ansi16 => {
+                let sgr = |index: u8, base: u8| match index {
+                    0..=7 => base + index,
+                    _ => base + 60 + index - 8,
+                };
+                (
+                    sgr(ansi_16(f), 30).to_string(),
+                    background.map(|b| sgr(ansi_16(b), 40).to_string()),
+                )
+            }
+            ColorDepth::Mono => return None,
+        };
+        let background = background.unwrap_or_else(|| "49".to_string()); // The terminal's own background
+        Some(format!("\x1b[{};{}m", foreground, background))
+    }
+    /// Writes the frame buffer to `out`. Terminal contexts are drawn from the top-left corner,
+    /// only rewriting the cells that changed since the last flush. Image contexts are written
+    /// whole, as lines of text.
+    pub fn flush<W: Write>(
+        &mut self,
+        out: &mut W,
+        color: bool,
+        webify: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut out = Counter { out, bytes: 0 };
+        let width = self.width.max(1);
+        let differential = !self.image && self.previous.len() == self.frame_buffer.len();
+        let mut style = None; // The colors last written, only written again when they change
+        let mut next = None; // Where the cursor is, if it's known
 
-        match (color, webify) {
-            (false, _) => {
-                let frame: String = self.frame_buffer.iter().map(|pixel| pixel.0).collect();
-                println!("{}", frame);
+        for (id, cell) in self.frame_buffer.iter().enumerate() {
+            if self.image && id > 0 && id % width == 0 {
+                writeln!(out)?;
             }
-            (true, false) => {
-                for pixel in &self.frame_buffer {
-                    let styled = style(pixel.0)
-                        .with(Color::Rgb {
-                            r: (pixel.1).0,
-                            g: (pixel.1).1,
-                            b: (pixel.1).2,
-                        })
-                        .on(Color::Rgb {
-                            r: 25,
-                            g: 25,
-                            b: 25,
-                        });
-                    stdout.queue(PrintStyledContent(styled))?;
-                }
+            if differential && self.previous[id] == *cell {
+                continue;
             }
-            (true, true) => {
-                for pixel in &self.frame_buffer {
-                    print!(
-                        "<span style=\"color:rgb({},{},{})\">{}",
-                        (pixel.1).0,
-                        (pixel.1).1,
-                        (pixel.1).2,
-                        pixel.0
-                    );
+            if !self.image && next != Some(id) {
+                out.queue(cursor::MoveTo((id % width) as u16, (id / width) as u16))?;
+            }
+            match (color, webify) {
+                (false, _) => write!(out, "{}", cell.glyph)?,
+                (true, false) => {
+                    let escape = self.escape(cell, id);
+                    if escape != style {
+                        if let Some(escape) = &escape {
+                            write!(out, "{}", escape)?;
+                        }
+                        style = escape;
+                    }
+                    write!(out, "{}", cell.glyph)?;
+                }
+                (true, true) => {
+                    let (r, g, b) = cell.foreground;
+                    match self.background_at(id) {
+                        Some((br, bg, bb)) => write!(
+                            out,
+                            "<span style=\"color:rgb({},{},{});background-color:rgb({},{},{})\">{}",
+                            r, g, b, br, bg, bb, cell.glyph
+                        )?,
+                        None => write!(
+                            out,
+                            "<span style=\"color:rgb({},{},{})\">{}",
+                            r, g, b, cell.glyph
+                        )?,
+                    }
                 }
             }
+            // The cursor stays put after the last column, until the next glyph wraps it
+            next = if (id + 1) % width == 0 {
+                None
+            } else {
+                Some(id + 1)
+            };
+        }
+        if style.is_some() {
+            write!(out, "\x1b[0m")?;
         }
+        if self.image {
+            writeln!(out)?;
+        } else {
+            self.previous = self.frame_buffer.clone();
+        }
+        self.bytes_flushed = out.bytes;
 
         Ok(())
     }
-    pub fn update(
-        &mut self,
-        mut old_size: (u16, u16),
-        meshes: &[SimpleMesh],
-    ) -> Result<(), Box<dyn Error>> {
-        let terminal_size = if self.image {
-            (self.width as u16, self.height as u16)
+    /// Forgets the last flushed frame, so the next flush redraws every cell. Call it after
+    /// something else has drawn over the terminal.
+    pub fn invalidate(&mut self) {
+        self.previous.clear();
+    }
+    /// Resizes the context to `size` (in cells) and looks through `camera`
+    pub fn update(&mut self, size: (usize, usize), camera: &Camera) {
+        self.width = size.0;
+        self.height = size.1;
+        let (width, height) = self.resolution();
+        let aspect = width.max(1) as f32 / height.max(1) as f32;
+        self.camera(camera.projection(aspect), camera.view());
+    }
+    /// Moves the scene's lights into view space, call it after the view changes
+    pub fn light(&mut self, lights: &[Light]) {
+        self.lights = if lights.is_empty() {
+            vec![Light::headlight()]
         } else {
-            terminal::size()?
+            lights
+                .iter()
+                .map(|light| light.transformed(&self.view))
+                .collect()
         };
+    }
+}
 
-        if old_size != terminal_size {
-            old_size = terminal_size; // It changed! Set new size
-            let mut scale: f32 = 0.0; // The scene's scale
-            for mesh in meshes {
-                // This calculates the maximum axis value (x y or z) in all meshes
-                scale = scale
-                    .max(mesh.bounding_box.max.x)
-                    .max(mesh.bounding_box.max.y)
-                    .max(mesh.bounding_box.max.z);
-            }
-            scale = f32::from(old_size.1).min(f32::from(old_size.0) / 2.0) / scale / 2.0; // Constrain to width and height, whichever is smaller
-            let t = Matrix4::new(
-                scale,
-                0.0,
-                0.0,
-                f32::from(old_size.0) / 4.0, // X translation is divided by 4 because there's a 1 char space between charxels
-                0.0,
-                -scale,
-                0.0,
-                f32::from(old_size.1) / 2.0, // Y translation is divided by 2 to center
-                0.0,
-                0.0,
-                scale,
-                0.0,
-                0.0,
-                0.0,
-                0.0,
-                1.0,
-            );
-            self.utransform = t;
-            if !self.image {
-                self.width = old_size.0 as usize;
-                self.height = (old_size.1) as usize;
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        Ok(())
+    fn red_cell() -> Context {
+        let mut context = Context::blank(true);
+        context.width = 1;
+        context.height = 1;
+        context.clear();
+        context.frame_buffer[0] = Cell {
+            glyph: '#',
+            foreground: (250, 0, 0),
+            background: None,
+        };
+        context
+    }
+
+    fn flushed(context: &mut Context) -> String {
+        let mut out = vec![];
+        context.flush(&mut out, true, false).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_flush_color_depths() {
+        let mut context = red_cell();
+        assert!(flushed(&mut context).contains("38;2;250;0;0"));
+        context.color_depth = ColorDepth::Ansi256;
+        assert!(flushed(&mut context).contains("38;5;196"));
+        context.color_depth = ColorDepth::Ansi16;
+        assert!(flushed(&mut context).starts_with("\x1b[91;40m#"));
+        context.color_depth = ColorDepth::Mono;
+        assert_eq!(flushed(&mut context), "#\n");
+    }
+
+    #[test]
+    fn test_flush_backgrounds() {
+        let mut context = red_cell();
+        context.background = Background::Transparent;
+        assert!(flushed(&mut context).starts_with("\x1b[38;2;250;0;0;49m#"));
+        context.background = Background::Solid((255, 255, 255));
+        assert!(flushed(&mut context).contains("48;2;255;255;255"));
+        let mut html = vec![];
+        context.flush(&mut html, true, true).unwrap();
+        assert!(String::from_utf8(html)
+            .unwrap()
+            .contains("background-color:rgb(255,255,255)"));
+    }
+
+    #[test]
+    fn test_differential_flush() {
+        let mut context = Context::blank(false);
+        context.width = 4;
+        context.height = 2;
+        context.clear();
+        let full = flushed(&mut context);
+        assert_eq!(context.bytes_flushed, full.len());
+        assert_eq!(full.matches("\x1b[38;2").count(), 1); // One style for the whole frame
+
+        assert_eq!(flushed(&mut context), ""); // Nothing changed
+        context.frame_buffer[5].glyph = '#';
+        context.frame_buffer[6].glyph = '#';
+        assert_eq!(
+            flushed(&mut context),
+            "\x1b[2;2H\x1b[38;2;0;0;0;48;2;25;25;25m##\x1b[0m"
+        );
+
+        context.invalidate();
+        assert_eq!(flushed(&mut context).len(), full.len());
     }
 }
@@ -0,0 +1,170 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent};
+use nalgebra::Vector2;
+
+use sloth::{
+    Camera, Cell, Context, Culling, PixelMode, Projection, Renderer, Scene, Shading, Turntable,
+};
+
+const ORBIT_STEP: f32 = 0.1; // Radians per key press
+const PAN_STEP: f32 = 0.05; // Of the distance to the target
+const ZOOM_STEP: f32 = 0.9;
+const SPEED_STEP: f32 = 1.25;
+
+const HELP: [&str; 7] = [
+    " arrows wasd  orbit        i j k l   pan            ",
+    " + - scroll   zoom         r         reset view     ",
+    " space        pause        [ ]       spin speed     ",
+    " m            pixel mode   g         shading        ",
+    " o            projection   b         culling        ",
+    " h ?          help         q esc     quit           ",
+    " mouse        left drag turns, right drag pans      ",
+];
+
+/// The keyboard controls of the terminal viewer
+pub struct Controls {
+    home: Camera, // Where resetting the view goes back to
+    pub paused: bool,
+    help: bool,
+    drag: Option<(u16, u16)>, // The cell the mouse was last dragged over
+}
+
+impl Controls {
+    pub fn new(camera: &Camera) -> Controls {
+        Controls {
+            home: camera.clone(),
+            paused: false,
+            help: false,
+            drag: None,
+        }
+    }
+    /// Acts on a key press, returns whether it asks to quit
+    pub fn key(
+        &mut self,
+        key: KeyEvent,
+        scene: &mut Scene,
+        renderer: &mut Renderer,
+        turntable: &mut Turntable,
+    ) -> bool {
+        let camera = &mut scene.camera;
+        let context = &mut renderer.context;
+        let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return true,
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Left if shift => camera.pan(-PAN_STEP, 0.0),
+            KeyCode::Right if shift => camera.pan(PAN_STEP, 0.0),
+            KeyCode::Up if shift => camera.pan(0.0, PAN_STEP),
+            KeyCode::Down if shift => camera.pan(0.0, -PAN_STEP),
+            KeyCode::Left | KeyCode::Char('a') => camera.orbit(-ORBIT_STEP, 0.0),
+            KeyCode::Right | KeyCode::Char('d') => camera.orbit(ORBIT_STEP, 0.0),
+            KeyCode::Up | KeyCode::Char('w') => camera.orbit(0.0, ORBIT_STEP),
+            KeyCode::Down | KeyCode::Char('s') => camera.orbit(0.0, -ORBIT_STEP),
+            KeyCode::Char('j') => camera.pan(-PAN_STEP, 0.0),
+            KeyCode::Char('l') => camera.pan(PAN_STEP, 0.0),
+            KeyCode::Char('i') => camera.pan(0.0, PAN_STEP),
+            KeyCode::Char('k') => camera.pan(0.0, -PAN_STEP),
+            KeyCode::Char('+') | KeyCode::Char('=') => camera.zoom(ZOOM_STEP),
+            KeyCode::Char('-') | KeyCode::Char('_') => camera.zoom(1.0 / ZOOM_STEP),
+            KeyCode::Char('r') => {
+                *camera = self.home.clone();
+                turntable.time = 0.0;
+            }
+            KeyCode::Char(' ') | KeyCode::Char('p') => self.paused = !self.paused,
+            KeyCode::Char('[') => turntable.velocity /= SPEED_STEP,
+            KeyCode::Char(']') => turntable.velocity *= SPEED_STEP,
+            KeyCode::Char('m') => {
+                context.mode = match context.mode {
+                    PixelMode::Glyph => PixelMode::HalfBlock,
+                    PixelMode::HalfBlock => PixelMode::Braille,
+                    PixelMode::Braille | PixelMode::Bitmap => PixelMode::Glyph,
+                }
+            }
+            KeyCode::Char('g') => {
+                context.shading = match context.shading {
+                    Shading::Flat => Shading::Gouraud,
+                    Shading::Gouraud => Shading::Phong,
+                    Shading::Phong => Shading::Flat,
+                }
+            }
+            KeyCode::Char('o') => {
+                camera.projection = match camera.projection {
+                    Projection::Perspective => Projection::Orthographic,
+                    Projection::Orthographic => Projection::Perspective,
+                }
+            }
+            KeyCode::Char('b') => {
+                context.culling = match context.culling {
+                    Culling::None => Culling::Back,
+                    Culling::Back => Culling::Front,
+                    Culling::Front => Culling::None,
+                }
+            }
+            KeyCode::Char('h') | KeyCode::Char('?') => self.help = !self.help,
+            _ => {}
+        }
+        false
+    }
+    /// Acts on the mouse: dragging with the left button turns the scene, the right button pans it
+    /// and scrolling zooms
+    pub fn mouse(&mut self, event: MouseEvent, scene: &mut Scene, context: &Context) {
+        let camera = &mut scene.camera;
+        // Cells are twice as tall as they're wide, measure everything in rows
+        let (width, height) = (context.width as f32 / 2.0, context.height as f32);
+        let radius = width.min(height).max(1.0) / 2.0;
+        let ball = |column: u16, row: u16| {
+            Vector2::new(
+                (column as f32 / 2.0 - width / 2.0) / radius,
+                (height / 2.0 - row as f32) / radius,
+            )
+        };
+        match event {
+            MouseEvent::Down(_, column, row, _) => self.drag = Some((column, row)),
+            MouseEvent::Up(..) => self.drag = None,
+            MouseEvent::Drag(button, column, row, _) => {
+                let (from_column, from_row) = self.drag.unwrap_or((column, row));
+                match button {
+                    MouseButton::Left => {
+                        camera.arcball(ball(from_column, from_row), ball(column, row))
+                    }
+                    MouseButton::Right => {
+                        // Visible height at the target, in distances to it
+                        let rows = 2.0 * (camera.fov / 2.0).tan() / height.max(1.0);
+                        let right = (from_column as f32 - column as f32) / 2.0 * rows;
+                        let up = (row as f32 - from_row as f32) * rows;
+                        camera.pan(right, up);
+                    }
+                    MouseButton::Middle => {}
+                }
+                self.drag = Some((column, row));
+            }
+            MouseEvent::ScrollUp(..) => camera.zoom(ZOOM_STEP),
+            MouseEvent::ScrollDown(..) => camera.zoom(1.0 / ZOOM_STEP),
+        }
+    }
+    /// Draws the help over the top-left corner of the frame, if it's shown
+    pub fn overlay(&self, context: &mut Context, speed: f32) {
+        if !self.help {
+            return;
+        }
+        let status = format!(
+            " {:?} pixels, {:?} shading, {:?} culling, {:.2} rad/s{} ",
+            context.mode,
+            context.shading,
+            context.culling,
+            speed,
+            if self.paused { ", paused" } else { "" }
+        );
+        for (row, line) in HELP.iter().chain([status.as_str()].iter()).enumerate() {
+            if row >= context.height {
+                break;
+            }
+            for (column, glyph) in line.chars().take(context.width).enumerate() {
+                context.frame_buffer[row * context.width + column] = Cell {
+                    glyph,
+                    foreground: (255, 255, 255),
+                    background: Some((0, 0, 0)),
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,519 @@
+use crate::color::{Background, ColorDepth};
+use crate::context::Context;
+use crate::font::{inked, GLYPH_HEIGHT, GLYPH_WIDTH};
+use png::{BitDepth, ColorType, Encoder};
+use std::error::Error;
+use std::io::Write;
+
+fn encode<W: Write>(
+    mut out: W,
+    (width, height): (usize, usize),
+    color: ColorType,
+    depth: BitDepth,
+    data: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    // The PNG writer ends the file when it's dropped, without a word if that fails, so it only
+    // ever writes to memory
+    let mut png = vec![];
+    {
+        let mut encoder = Encoder::new(&mut png, width as u32, height as u32);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        encoder.write_header()?.write_image_data(data)?;
+    }
+    out.write_all(&png)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// The last rendered pixels as RGBA, with the background behind the pixels nothing was drawn on
+fn pixel_rgba(context: &Context) -> ((usize, usize), Vec<u8>) {
+    let (width, height) = context.resolution();
+    let mut data = Vec::with_capacity(width * height * 4);
+    for (id, pixel) in context.pixels.iter().enumerate() {
+        let background = || context.background.color(id / width.max(1), height);
+        let (color, alpha) = match pixel {
+            Some(pixel) => (pixel.foreground, 255),
+            None => match background() {
+                Some(color) => (color, 255),
+                None => ((0, 0, 0), 0),
+            },
+        };
+        data.extend_from_slice(&[color.0, color.1, color.2, alpha]);
+    }
+    ((width, height), data)
+}
+
+/// The last rendered frame's glyphs as RGBA, drawn with the built-in font
+fn text_rgba(context: &Context) -> ((usize, usize), Vec<u8>) {
+    let (width, height) = (context.width * GLYPH_WIDTH, context.height * GLYPH_HEIGHT);
+    let mut data = vec![0; width * height * 4];
+    for (id, cell) in context.frame_buffer.iter().enumerate() {
+        let (column, row) = (id % context.width, id / context.width);
+        let background = context.background_at(id);
+        for y in 0..GLYPH_HEIGHT {
+            for x in 0..GLYPH_WIDTH {
+                let rgba = if inked(cell.glyph, x, y) {
+                    Some(cell.foreground)
+                } else {
+                    background
+                }
+                .map_or([0, 0, 0, 0], |(r, g, b)| [r, g, b, 255]);
+                let pixel = (row * GLYPH_HEIGHT + y) * width + column * GLYPH_WIDTH + x;
+                data[pixel * 4..pixel * 4 + 4].copy_from_slice(&rgba);
+            }
+        }
+    }
+    ((width, height), data)
+}
+
+/// Writes the last rendered pixels as an RGBA PNG, one image pixel per pixel. Pixels nothing was
+/// drawn on show the context's background, or are transparent without one.
+pub fn write_png<W: Write>(context: &Context, out: W) -> Result<(), Box<dyn Error>> {
+    let (size, data) = pixel_rgba(context);
+    encode(out, size, ColorType::RGBA, BitDepth::Eight, &data)
+}
+
+/// Writes the last rendered depths as a 16 bit grayscale PNG, white at the nearest pixel drawn and
+/// black past the farthest one
+pub fn write_depth_png<W: Write>(context: &Context, out: W) -> Result<(), Box<dyn Error>> {
+    let drawn = || context.z_buffer.iter().filter(|z| **z < f32::MAX);
+    let near = drawn().fold(f32::MAX, |near, z| near.min(*z));
+    let far = drawn().fold(f32::MIN, |far, z| far.max(*z));
+    let range = (far - near).max(f32::EPSILON);
+    let mut data = Vec::with_capacity(context.z_buffer.len() * 2);
+    for z in &context.z_buffer {
+        let value = if *z < f32::MAX {
+            1 + ((1.0 - (z - near) / range) * 65534.0) as u16
+        } else {
+            0
+        };
+        data.extend_from_slice(&value.to_be_bytes());
+    }
+    encode(
+        out,
+        context.resolution(),
+        ColorType::Grayscale,
+        BitDepth::Sixteen,
+        &data,
+    )
+}
+
+/// Writes the last rendered frame as an RGBA PNG of its glyphs, drawn with a built-in 8×16 bitmap
+/// font in their cells' colors. Looks like a screenshot of the terminal, whatever its font.
+pub fn write_text_png<W: Write>(context: &Context, out: W) -> Result<(), Box<dyn Error>> {
+    let (size, data) = text_rgba(context);
+    encode(out, size, ColorType::RGBA, BitDepth::Eight, &data)
+}
+
+/// Encodes rendered frames one after the other into an animated GIF
+pub struct GifWriter<W: Write> {
+    out: Option<W>, // Until the first frame gives the GIF its size
+    encoder: Option<gif::Encoder<W>>,
+    pub delay: u16,         // Between frames, in hundredths of a second
+    pub plays: Option<u16>, // How many times the animation plays, `None` for forever
+    pub pixels: bool,       // Draw the pixels rather than the glyphs
+}
+
+impl<W: Write> GifWriter<W> {
+    pub fn new(out: W) -> GifWriter<W> {
+        GifWriter {
+            out: Some(out),
+            encoder: None,
+            delay: 5,
+            plays: None,
+            pixels: false,
+        }
+    }
+    /// Adds the last rendered frame to the animation. Every frame must be the size of the first.
+    pub fn write_frame(&mut self, context: &Context) -> Result<(), Box<dyn Error>> {
+        let ((width, height), mut data) = if self.pixels {
+            pixel_rgba(context)
+        } else {
+            text_rgba(context)
+        };
+        if width > u16::MAX as usize || height > u16::MAX as usize {
+            return Err(format!("gif: {}x{} is too large", width, height).into());
+        }
+        let encoder = match (&mut self.encoder, self.out.take()) {
+            (Some(encoder), _) => encoder,
+            (None, Some(out)) => {
+                let mut encoder = gif::Encoder::new(out, width as u16, height as u16, &[])?;
+                encoder.set_repeat(match self.plays {
+                    Some(plays) => gif::Repeat::Finite(plays.saturating_sub(1)),
+                    None => gif::Repeat::Infinite,
+                })?;
+                self.encoder.get_or_insert(encoder)
+            }
+            (None, None) => return Err("gif: writer failed earlier".into()),
+        };
+        let mut frame = gif::Frame::from_rgba_speed(width as u16, height as u16, &mut data, 10);
+        frame.delay = self.delay;
+        frame.dispose = gif::DisposalMethod::Background; // Or transparent frames pile up
+        encoder.write_frame(&frame)?;
+        Ok(())
+    }
+    /// Ends the animation and flushes the writer, which it hands back
+    pub fn finish(mut self) -> Result<W, Box<dyn Error>> {
+        let mut out = match (self.encoder.take(), self.out.take()) {
+            (Some(encoder), _) => encoder.into_inner()?, // Writes the trailer
+            (None, Some(_)) => return Err("gif: no frames".into()),
+            (None, None) => return Err("gif: writer failed earlier".into()),
+        };
+        out.flush()?;
+        Ok(out)
+    }
+}
+
+/// `text` as a JSON string
+fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Records frames as an asciicast v2 file, which asciinema and other players replay in a terminal.
+/// Every frame is an output event holding what `Context::flush` writes to a terminal: only the
+/// cells that changed since the previous frame.
+pub struct CastWriter<W: Write> {
+    out: W,
+    width: usize,
+    height: usize,
+    frames: usize,
+}
+
+/// The `TERM` and `COLORTERM` of a terminal that shows the colors of `depth`
+fn term(depth: ColorDepth) -> &'static str {
+    match depth {
+        ColorDepth::TrueColor => r#""TERM": "xterm-256color", "COLORTERM": "truecolor""#,
+        ColorDepth::Ansi256 => r#""TERM": "xterm-256color""#,
+        ColorDepth::Ansi16 => r#""TERM": "xterm""#,
+        ColorDepth::Mono => r#""TERM": "vt100""#,
+    }
+}
+
+impl<W: Write> CastWriter<W> {
+    /// Starts a recording of a terminal `width` by `height` cells
+    pub fn new(out: W, width: usize, height: usize) -> CastWriter<W> {
+        CastWriter {
+            out,
+            width,
+            height,
+            frames: 0,
+        }
+    }
+    /// Flushes the last rendered frame into the recording, shown `time` seconds in. The context
+    /// must be a terminal one, not an image one, and keeps track of the cells already recorded.
+    pub fn write_frame(
+        &mut self,
+        context: &mut Context,
+        color: bool,
+        time: f32,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut data = vec![];
+        if self.frames == 0 {
+            // The header names a terminal for the colors the frames actually use
+            let depth = if color {
+                context.color_depth
+            } else {
+                ColorDepth::Mono
+            };
+            writeln!(
+                self.out,
+                r#"{{"version": 2, "width": {}, "height": {}, "env": {{{}}}}}"#,
+                self.width,
+                self.height,
+                term(depth)
+            )?;
+            write!(data, "\x1b[?25l\x1b[2J")?; // Hide the cursor and start from a clear screen
+        }
+        context.flush(&mut data, color, false)?;
+        writeln!(
+            self.out,
+            r#"[{:.6}, "o", {}]"#,
+            time,
+            json_string(&String::from_utf8_lossy(&data))
+        )?;
+        self.frames += 1;
+        Ok(())
+    }
+    /// Flushes the recording, and hands its writer back
+    pub fn finish(mut self) -> Result<W, Box<dyn Error>> {
+        if self.frames == 0 {
+            return Err("cast: no frames".into());
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Writes the last rendered frame as an SVG, a `<text>` element per row with a `<tspan>` per run of
+/// one color. Cells are 8 by 16 units and the text is stretched to fit them, so it stays aligned
+/// whichever monospace font the viewer picks.
+pub fn write_svg<W: Write>(context: &Context, mut out: W) -> Result<(), Box<dyn Error>> {
+    let (width, height) = (context.width * GLYPH_WIDTH, context.height * GLYPH_HEIGHT);
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        width, height
+    )?;
+    match context.background {
+        Background::Solid(color) => writeln!(
+            out,
+            r#"<rect width="100%" height="100%" fill="{}"/>"#,
+            hex(color)
+        )?,
+        Background::Gradient(top, bottom) => {
+            writeln!(
+                out,
+                r#"<defs><linearGradient id="background" x2="0" y2="1"><stop offset="0" stop-color="{}"/><stop offset="1" stop-color="{}"/></linearGradient></defs>"#,
+                hex(top),
+                hex(bottom)
+            )?;
+            writeln!(
+                out,
+                r#"<rect width="100%" height="100%" fill="url(#background)"/>"#
+            )?;
+        }
+        Background::Transparent => {}
+    }
+    let rows = context.frame_buffer.chunks(context.width.max(1));
+    // Cells with backgrounds of their own, in runs along each row
+    for (row, cells) in rows.clone().enumerate() {
+        let mut column = 0;
+        while column < cells.len() {
+            let background = cells[column].background;
+            let run = cells[column..]
+                .iter()
+                .take_while(|cell| cell.background == background)
+                .count();
+            if let Some(color) = background {
+                writeln!(
+                    out,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                    column * GLYPH_WIDTH,
+                    row * GLYPH_HEIGHT,
+                    run * GLYPH_WIDTH,
+                    GLYPH_HEIGHT,
+                    hex(color)
+                )?;
+            }
+            column += run;
+        }
+    }
+    writeln!(
+        out,
+        r#"<g font-family="monospace" font-size="{:.2}" xml:space="preserve">"#,
+        GLYPH_WIDTH as f32 / 0.6 // Monospace glyphs are about 0.6 em wide
+    )?;
+    for (row, cells) in rows.enumerate() {
+        let length = match cells.iter().rposition(|cell| cell.glyph != ' ') {
+            Some(last) => last + 1,
+            None => continue,
+        };
+        write!(
+            out,
+            r#"<text y="{}" textLength="{}" lengthAdjust="spacingAndGlyphs">"#,
+            row * GLYPH_HEIGHT + GLYPH_HEIGHT * 3 / 4,
+            length * GLYPH_WIDTH
+        )?;
+        let mut run: Option<(u8, u8, u8)> = None;
+        for cell in &cells[..length] {
+            // Spaces don't show their color, they join whichever run they're in
+            if cell.glyph != ' ' && run != Some(cell.foreground) {
+                if run.is_some() {
+                    write!(out, "</tspan>")?;
+                }
+                write!(out, r#"<tspan fill="{}">"#, hex(cell.foreground))?;
+                run = Some(cell.foreground);
+            }
+            match cell.glyph {
+                '&' => write!(out, "&amp;")?,
+                '<' => write!(out, "&lt;")?,
+                '>' => write!(out, "&gt;")?,
+                glyph => write!(out, "{}", glyph)?,
+            }
+        }
+        writeln!(out, "</tspan></text>")?;
+    }
+    writeln!(out, "</g>")?;
+    writeln!(out, "</svg>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Background;
+    use crate::context::{Cell, PixelMode};
+
+    fn drawn() -> Context {
+        let mut context = Context::blank(true);
+        context.mode = PixelMode::Bitmap;
+        context.width = 3;
+        context.height = 2;
+        context.clear();
+        for (id, z) in [(0, 0.5), (4, -0.5)].iter() {
+            context.z_buffer[*id] = *z;
+            context.pixels[*id] = Some(Cell {
+                glyph: '#',
+                foreground: (255, 0, 0),
+                background: None,
+            });
+        }
+        context
+    }
+
+    fn decode(data: &[u8]) -> (png::OutputInfo, Vec<u8>) {
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::IDENTITY);
+        let (info, mut reader) = decoder.read_info().unwrap();
+        let mut pixels = vec![0; info.buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        (info, pixels)
+    }
+
+    #[test]
+    fn test_png() {
+        let mut context = drawn();
+        context.background = Background::Transparent;
+        let mut data = vec![];
+        write_png(&context, &mut data).unwrap();
+        let (info, pixels) = decode(&data);
+        assert_eq!((info.width, info.height), (3, 2));
+        assert_eq!(pixels[..4], [255, 0, 0, 255]);
+        assert_eq!(pixels[4..8], [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_depth_png() {
+        let mut data = vec![];
+        write_depth_png(&drawn(), &mut data).unwrap();
+        let (info, pixels) = decode(&data);
+        assert_eq!(info.bit_depth, BitDepth::Sixteen);
+        let depth = |id: usize| u16::from_be_bytes([pixels[id * 2], pixels[id * 2 + 1]]);
+        assert_eq!(depth(4), 65535); // Nearest
+        assert_eq!(depth(0), 1); // Farthest
+        assert_eq!(depth(1), 0); // Empty
+    }
+
+    fn text() -> Context {
+        let mut context = Context::blank(true);
+        context.width = 3;
+        context.height = 1;
+        context.clear();
+        context.frame_buffer[0] = Cell {
+            glyph: '█',
+            foreground: (255, 0, 0),
+            background: None,
+        };
+        context.frame_buffer[1] = Cell {
+            glyph: '<',
+            foreground: (0, 255, 0),
+            background: Some((0, 0, 255)),
+        };
+        context
+    }
+
+    #[test]
+    fn test_text_png() {
+        let mut data = vec![];
+        write_text_png(&text(), &mut data).unwrap();
+        let (info, pixels) = decode(&data);
+        assert_eq!((info.width, info.height), (24, 16));
+        let pixel = |x: usize, y: usize| &pixels[(y * 24 + x) * 4..(y * 24 + x) * 4 + 4];
+        assert_eq!(pixel(4, 8), [255, 0, 0, 255]); // Full block
+        assert_eq!(pixel(8, 15), [0, 0, 255, 255]); // The cell's own background
+        assert_eq!(pixel(20, 8), [25, 25, 25, 255]); // The context's background
+    }
+
+    #[test]
+    fn test_svg() {
+        let mut data = vec![];
+        write_svg(&text(), &mut data).unwrap();
+        let svg = String::from_utf8(data).unwrap();
+        assert!(svg.contains(r##"<rect width="100%" height="100%" fill="#191919"/>"##));
+        assert!(svg.contains(r##"<rect x="8" y="0" width="8" height="16" fill="#0000ff"/>"##));
+        assert!(svg.contains(r##"textLength="16""##)); // The trailing space is left out
+        assert!(svg.contains(
+            r##"<tspan fill="#ff0000">█</tspan><tspan fill="#00ff00">&lt;</tspan></text>"##
+        ));
+    }
+
+    #[test]
+    fn test_gif() {
+        let mut data = vec![];
+        let mut gif = GifWriter::new(&mut data);
+        gif.delay = 10;
+        gif.write_frame(&text()).unwrap();
+        gif.write_frame(&text()).unwrap();
+        gif.finish().unwrap();
+        let mut decoder = gif::DecodeOptions::new().read_info(&data[..]).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (24, 16));
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(frame.delay, 10);
+            frames += 1;
+        }
+        assert_eq!(frames, 2);
+
+        struct Full;
+        impl Write for Full {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::WriteZero.into())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut gif = GifWriter::new(Full);
+        assert!(gif.write_frame(&text()).is_err());
+        assert!(gif.write_frame(&text()).is_err()); // Rather than panicking
+        assert!(gif.finish().is_err());
+    }
+
+    #[test]
+    fn test_cast() {
+        let mut context = text();
+        context.image = false;
+        let mut data = vec![];
+        let mut cast = CastWriter::new(&mut data, 3, 1);
+        cast.write_frame(&mut context, false, 0.0).unwrap();
+        context.frame_buffer[2].glyph = '"';
+        cast.write_frame(&mut context, false, 0.5).unwrap();
+        cast.finish().unwrap();
+        let cast = String::from_utf8(data).unwrap();
+        let lines: Vec<&str> = cast.lines().collect();
+        assert_eq!(
+            lines[0],
+            r#"{"version": 2, "width": 3, "height": 1, "env": {"TERM": "vt100"}}"# // No colors
+        );
+        assert_eq!(
+            lines[1],
+            r#"[0.000000, "o", "\u001b[?25l\u001b[2J\u001b[1;1H█< "]"#
+        );
+        assert_eq!(lines[2], r#"[0.500000, "o", "\u001b[1;3H\""]"#); // Only the cell that changed
+
+        context.color_depth = ColorDepth::Ansi16;
+        let mut cast = CastWriter::new(vec![], 3, 1);
+        cast.write_frame(&mut context, true, 0.0).unwrap();
+        let data = cast.finish().unwrap();
+        assert!(data
+            .starts_with(br#"{"version": 2, "width": 3, "height": 1, "env": {"TERM": "xterm"}}"#));
+        assert!(CastWriter::new(vec![], 3, 1).finish().is_err());
+    }
+}
@@ -1,5 +1,10 @@
-use nalgebra::{Matrix4, Unit, Vector4};
+use crate::lighting::Specular;
+use crate::stl::STL_COLOR;
+use crate::texture::Texture;
+use nalgebra::{Matrix4, Unit, Vector2, Vector3, Vector4};
 use std::clone::Clone;
+use std::collections::HashMap;
+use std::sync::Arc;
 use tobj::{Material, Mesh};
 
 #[derive(PartialEq, Debug)]
@@ -12,14 +17,38 @@ impl AABB {
     pub fn new(min: Vector4<f32>, max: Vector4<f32>) -> AABB {
         AABB { min, max }
     }
+    /// A box around nothing, to `extend` around triangles
+    pub fn empty() -> AABB {
+        AABB::new(
+            Vector4::new(f32::MAX, f32::MAX, f32::MAX, 1.0),
+            Vector4::new(f32::MIN, f32::MIN, f32::MIN, 1.0),
+        )
+    }
+    /// Grows the box to fit `triangle`
+    pub fn extend(&mut self, triangle: &Triangle) {
+        let aabb = triangle.aabb();
+        for axis in 0..3 {
+            self.min[axis] = aabb.min[axis].min(self.min[axis]);
+            self.max[axis] = aabb.max[axis].max(self.max[axis]);
+        }
+    }
 }
 
+/// A colored triangle. `n1`, `n2` and `n3` are the vertex normals, they're zero when the model
+/// didn't come with any (see `SimpleMesh::smooth_normals`), in which case it's shaded flat.
+/// `uv` holds the texture coordinates of the three vertices, if the model has them, and `colors`
+/// their own colors, blended across the triangle instead of `color`.
 #[derive(PartialEq, Debug)]
 pub struct Triangle {
     pub color: (u8, u8, u8),
     pub v1: Vector4<f32>,
     pub v2: Vector4<f32>,
     pub v3: Vector4<f32>,
+    pub n1: Vector4<f32>,
+    pub n2: Vector4<f32>,
+    pub n3: Vector4<f32>,
+    pub uv: Option<[Vector2<f32>; 3]>,
+    pub colors: Option<[(u8, u8, u8); 3]>,
 }
 
 impl Default for Triangle {
@@ -29,6 +58,11 @@ impl Default for Triangle {
             v1: Vector4::new(1.0, -1.0, -1.0, 1.0),
             v2: Vector4::new(-1.0, -1.0, 1.0, 1.0),
             v3: Vector4::new(1.0, 1.0, -1.0, 1.0),
+            n1: Vector4::zeros(),
+            n2: Vector4::zeros(),
+            n3: Vector4::zeros(),
+            uv: None,
+            colors: None,
         }
     }
 }
@@ -40,12 +74,38 @@ impl Triangle {
             Vector4::from_fn(|x, _size| self.v1[x].max(self.v2[x].max(self.v3[x]))),
         )
     }
+    /// Transforms the vertices and normals. Normals have a w of 0, so they're only rotated and
+    /// scaled, which is right as long as the transform doesn't scale unevenly.
     pub fn mul(&mut self, transform: Matrix4<f32>) -> &mut Triangle {
         self.v1 = transform * self.v1;
         self.v2 = transform * self.v2;
         self.v3 = transform * self.v3;
+        self.n1 = transform * self.n1;
+        self.n2 = transform * self.n2;
+        self.n3 = transform * self.n3;
         self
     }
+    /// The color at `barycentric` coordinates inside of the triangle
+    pub fn color_at(&self, barycentric: &Vector3<f32>) -> (u8, u8, u8) {
+        match self.colors {
+            Some([c1, c2, c3]) => {
+                let mix = |a: u8, b: u8, c: u8| {
+                    (a as f32 * barycentric.x + b as f32 * barycentric.y + c as f32 * barycentric.z)
+                        .round()
+                        .clamp(0.0, 255.0) as u8
+                };
+                (
+                    mix(c1.0, c2.0, c3.0),
+                    mix(c1.1, c2.1, c3.1),
+                    mix(c1.2, c2.2, c3.2),
+                )
+            }
+            None => self.color,
+        }
+    }
+    pub fn has_normals(&self) -> bool {
+        self.n1 != Vector4::zeros() || self.n2 != Vector4::zeros() || self.n3 != Vector4::zeros()
+    }
     pub fn normal(&self) -> Unit<Vector4<f32>> {
         let v1 = self.v2 - self.v1;
         let v2 = self.v3 - self.v1;
@@ -63,6 +123,11 @@ impl Clone for Triangle {
             v1: self.v1,
             v2: self.v2,
             v3: self.v3,
+            n1: self.n1,
+            n2: self.n2,
+            n3: self.n3,
+            uv: self.uv,
+            colors: self.colors,
         }
     }
 }
@@ -78,6 +143,71 @@ pub trait ToSimpleMeshWithMaterial {
 pub struct SimpleMesh {
     pub bounding_box: AABB,
     pub triangles: Vec<Triangle>,
+    pub specular: Specular,
+    pub texture: Option<Arc<Texture>>, // Multiplies the triangles' colors, where they have uv
+}
+
+impl SimpleMesh {
+    /// A mesh of `triangles` with a bounding box around them, without a texture or highlights
+    pub fn new(triangles: Vec<Triangle>) -> SimpleMesh {
+        let mut bounding_box = AABB::empty();
+        for triangle in &triangles {
+            bounding_box.extend(triangle);
+        }
+        SimpleMesh {
+            bounding_box,
+            triangles,
+            specular: Specular::default(),
+            texture: None,
+        }
+    }
+
+    /// Paints every triangle `color`, in place of their vertex colors and the texture
+    pub fn recolor(&mut self, color: (u8, u8, u8)) {
+        for triangle in &mut self.triangles {
+            triangle.color = color;
+            triangle.colors = None;
+        }
+        self.texture = None;
+    }
+
+    /// Gives every triangle without vertex normals smooth ones, averaged from the faces around
+    /// each vertex. Faces meeting at more than `angle` radians keep a hard edge between them.
+    pub fn smooth_normals(&mut self, angle: f32) {
+        let threshold = angle.cos();
+        let key = |v: &Vector4<f32>| [v.x.to_bits(), v.y.to_bits(), v.z.to_bits()];
+        let mut faces = Vec::with_capacity(self.triangles.len()); // Area weighted face normals
+        let mut corners: HashMap<[u32; 3], Vec<usize>> = HashMap::new(); // The faces touching each position
+        for (id, triangle) in self.triangles.iter().enumerate() {
+            let edge1 = (triangle.v2 - triangle.v1).xyz();
+            let edge2 = (triangle.v3 - triangle.v1).xyz();
+            faces.push(edge1.cross(&edge2));
+            for vertex in &[triangle.v1, triangle.v2, triangle.v3] {
+                corners.entry(key(vertex)).or_default().push(id);
+            }
+        }
+
+        for (id, triangle) in self.triangles.iter_mut().enumerate() {
+            if triangle.has_normals() || faces[id] == Vector3::zeros() {
+                continue;
+            }
+            let face = faces[id].normalize();
+            let smooth = |vertex: &Vector4<f32>| {
+                let mut normal = Vector3::zeros();
+                for &other in &corners[&key(vertex)] {
+                    if faces[other] != Vector3::zeros()
+                        && faces[other].normalize().dot(&face) >= threshold
+                    {
+                        normal += faces[other];
+                    }
+                }
+                normal.normalize().insert_row(3, 0.0)
+            };
+            triangle.n1 = smooth(&triangle.v1);
+            triangle.n2 = smooth(&triangle.v2);
+            triangle.n3 = smooth(&triangle.v3);
+        }
+    }
 }
 
 impl ToSimpleMeshWithMaterial for Mesh {
@@ -86,12 +216,15 @@ impl ToSimpleMeshWithMaterial for Mesh {
             min: Vector4::new(0.0, 0.0, 0.0, 1.0),
             max: Vector4::new(0.0, 0.0, 0.0, 1.0),
         };
+        let mut specular = Specular::default();
+        if let Some(material) = self.material_id.and_then(|id| materials.get(id)) {
+            specular.color = Vector3::from(material.specular);
+            specular.shininess = material.shininess;
+        }
         let mut triangles = vec![
             Triangle {
                 color: (1, 1, 1),
-                v1: Vector4::new(0.0, 0.0, 0.0, 1.0),
-                v2: Vector4::new(0.0, 0.0, 0.0, 1.0),
-                v3: Vector4::new(0.0, 0.0, 0.0, 1.0)
+                ..Triangle::default()
             };
             self.indices.len() / 3
         ];
@@ -105,6 +238,27 @@ impl ToSimpleMeshWithMaterial for Mesh {
             tri.v3.x = self.positions[(self.indices[x * 3 + 2] * 3) as usize];
             tri.v3.y = self.positions[(self.indices[x * 3 + 2] * 3 + 1) as usize];
             tri.v3.z = self.positions[(self.indices[x * 3 + 2] * 3 + 2) as usize];
+            if !self.normals.is_empty() {
+                let normal = |corner: usize| {
+                    let id = (self.indices[x * 3 + corner] * 3) as usize;
+                    Vector4::new(
+                        self.normals[id],
+                        self.normals[id + 1],
+                        self.normals[id + 2],
+                        0.0,
+                    )
+                };
+                tri.n1 = normal(0);
+                tri.n2 = normal(1);
+                tri.n3 = normal(2);
+            }
+            if !self.texcoords.is_empty() {
+                let uv = |corner: usize| {
+                    let id = (self.indices[x * 3 + corner] * 2) as usize;
+                    Vector2::new(self.texcoords[id], self.texcoords[id + 1])
+                };
+                tri.uv = Some([uv(0), uv(1), uv(2)]);
+            }
 
             if !materials.is_empty() {
                 let material = &materials[self.material_id.unwrap()];
@@ -126,6 +280,8 @@ impl ToSimpleMeshWithMaterial for Mesh {
         SimpleMesh {
             triangles,
             bounding_box,
+            specular,
+            texture: None,
         }
     }
 }
@@ -140,19 +296,17 @@ impl ToSimpleMesh for Mesh {
 impl ToSimpleMesh for stl_io::IndexedMesh {
     fn to_simple_mesh(&self) -> SimpleMesh {
         let mut bounding_box = AABB {
-            min: Vector4::new(std::f32::MAX, std::f32::MAX, std::f32::MAX, 1.0),
-            max: Vector4::new(std::f32::MIN, std::f32::MIN, std::f32::MIN, 1.0),
+            min: Vector4::new(f32::MAX, f32::MAX, f32::MAX, 1.0),
+            max: Vector4::new(f32::MIN, f32::MIN, f32::MIN, 1.0),
         };
         fn stlv2v4(stlio_vec: [f32; 3]) -> Vector4<f32> {
             Vector4::new(stlio_vec[0], stlio_vec[1], stlio_vec[2], 1.0)
         }
         let mut triangles = vec![
             Triangle {
-                // at time of writing, stl_io lacked color
-                color: (0xFF, 0xFF, 0x00),
-                v1: Vector4::new(0.0, 0.0, 0.0, 1.0),
-                v2: Vector4::new(0.0, 0.0, 0.0, 1.0),
-                v3: Vector4::new(0.0, 0.0, 0.0, 1.0)
+                // stl_io drops the attribute bytes, see `Stl` for colors
+                color: STL_COLOR,
+                ..Triangle::default()
             };
             self.faces.len()
         ];
@@ -173,6 +327,8 @@ impl ToSimpleMesh for stl_io::IndexedMesh {
         SimpleMesh {
             triangles,
             bounding_box,
+            specular: Specular::default(),
+            texture: None,
         }
     }
 }
@@ -208,6 +364,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_vertex_colors() {
+        let mut triangle = Triangle {
+            color: (9, 9, 9),
+            ..Triangle::default()
+        };
+        let middle = Vector3::new(0.5, 0.5, 0.0);
+        assert_eq!(triangle.color_at(&middle), (9, 9, 9));
+        triangle.colors = Some([(255, 0, 0), (0, 255, 0), (0, 0, 255)]);
+        assert_eq!(triangle.color_at(&middle), (128, 128, 0));
+        assert_eq!(triangle.color_at(&Vector3::z()), (0, 0, 255));
+
+        let mut mesh = SimpleMesh {
+            bounding_box: triangle.aabb(),
+            triangles: vec![triangle],
+            specular: Specular::default(),
+            texture: None,
+        };
+        mesh.recolor((1, 2, 3));
+        assert_eq!(mesh.triangles[0].color_at(&middle), (1, 2, 3));
+    }
+
     #[test]
     fn test_normal() {
         let triangle = Triangle {
@@ -215,10 +393,56 @@ mod tests {
             v1: Vector4::new(-1.0, 1.0, 0.0, 1.0),
             v2: Vector4::new(0.0, 1.0, 1.0, 1.0),
             v3: Vector4::new(1.0, 1.0, 0.0, 1.0),
+            ..Triangle::default()
         };
         assert_eq!(
             triangle.normal(),
             Unit::new_normalize(Vector4::new(0.0, 1.0, 0.0, 0.0))
         );
     }
+
+    fn ridge(height: f32) -> SimpleMesh {
+        // Two triangles folded along the Z axis, like a roof
+        let vertex = |x, y, z| Vector4::new(x, y, z, 1.0);
+        SimpleMesh {
+            bounding_box: AABB::new(vertex(-1.0, 0.0, 0.0), vertex(1.0, height, 1.0)),
+            triangles: vec![
+                Triangle {
+                    v1: vertex(0.0, height, 0.0),
+                    v2: vertex(0.0, height, 1.0),
+                    v3: vertex(1.0, 0.0, 0.0),
+                    ..Triangle::default()
+                },
+                Triangle {
+                    v1: vertex(0.0, height, 1.0),
+                    v2: vertex(0.0, height, 0.0),
+                    v3: vertex(-1.0, 0.0, 0.0),
+                    ..Triangle::default()
+                },
+            ],
+            specular: Specular::default(),
+            texture: None,
+        }
+    }
+
+    #[test]
+    fn test_smooth_normals() {
+        let mut mesh = ridge(0.5);
+        mesh.smooth_normals(std::f32::consts::FRAC_PI_2);
+        let shared = mesh.triangles[0].n1;
+        assert!((shared - Vector4::new(0.0, 1.0, 0.0, 0.0)).norm() < 1e-6);
+        assert_eq!(mesh.triangles[1].n2, shared);
+        // Corners that aren't shared keep the face normal
+        let face = mesh.triangles[0].normal().into_inner();
+        assert!((mesh.triangles[0].n3 - face).norm() < 1e-6);
+    }
+
+    #[test]
+    fn test_hard_edges() {
+        let mut mesh = ridge(2.0); // Steep enough for the faces to meet at more than 45°
+        mesh.smooth_normals(std::f32::consts::FRAC_PI_4);
+        let face = mesh.triangles[0].normal().into_inner();
+        assert!((mesh.triangles[0].n1 - face).norm() < 1e-6);
+        assert!((mesh.triangles[0].n2 - face).norm() < 1e-6);
+    }
 }
@@ -0,0 +1,367 @@
+use crate::camera::{Camera, Projection};
+use crate::geometry::{SimpleMesh, Triangle};
+use crate::texture::{modulate, Texture};
+use gltf::image::Format;
+use nalgebra::{Matrix4, Point3, Vector2, Vector3, Vector4};
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+/// The meshes and cameras of a glTF or GLB file's scene, with their nodes' transforms applied.
+/// Buffers and images come from the file itself, data URIs, or files next to it.
+pub struct GltfScene {
+    pub meshes: Vec<SimpleMesh>, // One per primitive
+    pub cameras: Vec<Camera>,
+}
+
+/// A linear color channel from 0 to 1, as sRGB from 0 to 255
+fn srgb(linear: f32) -> u8 {
+    let linear = linear.clamp(0.0, 1.0);
+    let encoded = if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+fn texture(image: &gltf::image::Data) -> Option<Texture> {
+    let channels = match image.format {
+        Format::R8 => 1,
+        Format::R8G8 => 2,
+        Format::R8G8B8 => 3,
+        Format::R8G8B8A8 => 4,
+        _ => return None, // 16 bit and float images are rare in base colors
+    };
+    let pixels = image
+        .pixels
+        .chunks_exact(channels)
+        .map(|pixel| match channels {
+            1 | 2 => (pixel[0], pixel[0], pixel[0]), // Gray, and alpha
+            _ => (pixel[0], pixel[1], pixel[2]),
+        })
+        .collect();
+    Some(Texture {
+        width: image.width as usize,
+        height: image.height as usize,
+        pixels,
+    })
+}
+
+/// A camera where the node at `transform` puts it, looking down its -Z axis. Its target is as far
+/// ahead as the scene's `center`, so orbiting goes around the scene.
+fn camera(camera: &gltf::Camera, transform: &Matrix4<f32>, center: &Point3<f32>) -> Camera {
+    let position = transform.transform_point(&Point3::origin());
+    let forward = transform.transform_vector(&-Vector3::z()).normalize();
+    let distance = (center - position).dot(&forward).max(1e-3);
+    let mut result = Camera {
+        position,
+        target: position + forward * distance,
+        up: transform.transform_vector(&Vector3::y()).normalize(),
+        ..Camera::default()
+    };
+    match camera.projection() {
+        gltf::camera::Projection::Perspective(perspective) => {
+            result.fov = perspective.yfov();
+            result.near = perspective.znear();
+            result.far = perspective.zfar().unwrap_or(result.near * 1e5);
+        }
+        gltf::camera::Projection::Orthographic(orthographic) => {
+            result.projection = Projection::Orthographic;
+            result.fov = 2.0 * (orthographic.ymag() / distance).atan(); // Frames ymag at the target
+            result.near = orthographic.znear().max(1e-3);
+            result.far = orthographic.zfar();
+        }
+    }
+    result
+}
+
+impl GltfScene {
+    /// Loads a .gltf or .glb file
+    pub fn load(path: &Path) -> Result<GltfScene, Box<dyn Error>> {
+        let (document, buffers, images) = gltf::import(path)?;
+        GltfScene::from_document(&document, &buffers, &images)
+    }
+    /// Reads a GLB file, or a glTF file with every buffer and image in data URIs
+    pub fn from_slice(data: &[u8]) -> Result<GltfScene, Box<dyn Error>> {
+        let (document, buffers, images) = gltf::import_slice(data)?;
+        GltfScene::from_document(&document, &buffers, &images)
+    }
+    fn from_document(
+        document: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+        images: &[gltf::image::Data],
+    ) -> Result<GltfScene, Box<dyn Error>> {
+        let textures: Vec<Option<Arc<Texture>>> = images
+            .iter()
+            .map(|image| texture(image).map(Arc::new))
+            .collect();
+        let scene = match document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+        {
+            Some(scene) => scene,
+            None => {
+                return Ok(GltfScene {
+                    meshes: vec![],
+                    cameras: vec![],
+                })
+            }
+        };
+
+        // Every node with its transform into the scene, parents before their children
+        let mut nodes = vec![];
+        let mut stack: Vec<(gltf::Node, Matrix4<f32>)> = scene
+            .nodes()
+            .map(|node| (node, Matrix4::identity()))
+            .collect();
+        stack.reverse(); // Popped in the file's order
+        while let Some((node, parent)) = stack.pop() {
+            let local = node.transform().matrix();
+            let transform = parent * Matrix4::from_fn(|row, column| local[column][row]);
+            let children: Vec<gltf::Node> = node.children().collect();
+            for child in children.into_iter().rev() {
+                stack.push((child, transform));
+            }
+            nodes.push((node, transform));
+        }
+
+        let mut meshes = vec![];
+        for (node, transform) in &nodes {
+            let mesh = match node.mesh() {
+                Some(mesh) => mesh,
+                None => continue,
+            };
+            // Normals go through the inverse transpose, to stay perpendicular under uneven scales
+            let normal_transform = transform
+                .try_inverse()
+                .map_or(*transform, |inverse| inverse.transpose());
+            for primitive in mesh.primitives() {
+                let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|b| &b[..]));
+                let positions: Vec<Vector4<f32>> = match reader.read_positions() {
+                    Some(positions) => positions
+                        .map(|[x, y, z]| transform * Vector4::new(x, y, z, 1.0))
+                        .collect(),
+                    None => continue,
+                };
+                let normals: Option<Vec<Vector4<f32>>> = reader.read_normals().map(|normals| {
+                    normals
+                        .map(|[x, y, z]| {
+                            let normal = normal_transform * Vector4::new(x, y, z, 0.0);
+                            normal.xyz().normalize().insert_row(3, 0.0)
+                        })
+                        .collect()
+                });
+                let colors: Option<Vec<(u8, u8, u8)>> = reader.read_colors(0).map(|colors| {
+                    colors
+                        .into_rgb_f32()
+                        .map(|[r, g, b]| (srgb(r), srgb(g), srgb(b)))
+                        .collect()
+                });
+
+                let counts = [
+                    normals.as_ref().map(Vec::len),
+                    colors.as_ref().map(Vec::len),
+                ];
+                if counts
+                    .iter()
+                    .flatten()
+                    .any(|count| *count != positions.len())
+                {
+                    return Err("gltf: normals or colors don't match the positions".into());
+                }
+
+                let pbr = primitive.material().pbr_metallic_roughness();
+                let [r, g, b, _] = pbr.base_color_factor();
+                let color = (srgb(r), srgb(g), srgb(b));
+                let (texture, uvs) = match pbr.base_color_texture() {
+                    Some(info) => (
+                        textures
+                            .get(info.texture().source().index())
+                            .cloned()
+                            .flatten(),
+                        reader.read_tex_coords(info.tex_coord()).map(|uvs| {
+                            uvs.into_f32()
+                                .map(|[u, v]| Vector2::new(u, v))
+                                .collect::<Vec<_>>()
+                        }),
+                    ),
+                    None => (None, None),
+                };
+
+                let indices: Vec<usize> = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().map(|index| index as usize).collect(),
+                    None => (0..positions.len()).collect(),
+                };
+                if let Some(index) = indices.iter().find(|index| **index >= positions.len()) {
+                    return Err(format!("gltf: index {} is out of bounds", index).into());
+                }
+                let corners: Vec<[usize; 3]> = match primitive.mode() {
+                    gltf::mesh::Mode::Triangles => indices
+                        .chunks_exact(3)
+                        .map(|corners| [corners[0], corners[1], corners[2]])
+                        .collect(),
+                    gltf::mesh::Mode::TriangleStrip => (2..indices.len())
+                        .map(|i| match i % 2 {
+                            0 => [indices[i - 2], indices[i - 1], indices[i]],
+                            _ => [indices[i - 1], indices[i - 2], indices[i]], // Keeps the winding
+                        })
+                        .collect(),
+                    gltf::mesh::Mode::TriangleFan => (2..indices.len())
+                        .map(|i| [indices[0], indices[i - 1], indices[i]])
+                        .collect(),
+                    _ => continue, // Points and lines
+                };
+
+                let mut triangles = Vec::with_capacity(corners.len());
+                for [a, b, c] in corners {
+                    let mut triangle = Triangle {
+                        color,
+                        v1: positions[a],
+                        v2: positions[b],
+                        v3: positions[c],
+                        ..Triangle::default()
+                    };
+                    if let Some(normals) = &normals {
+                        triangle.n1 = normals[a];
+                        triangle.n2 = normals[b];
+                        triangle.n3 = normals[c];
+                    }
+                    if let Some(colors) = &colors {
+                        // Vertex colors multiply the base color
+                        triangle.colors = Some([a, b, c].map(|i| modulate(colors[i], color)));
+                    }
+                    if let Some(uvs) = &uvs {
+                        if [a, b, c].iter().all(|i| *i < uvs.len()) {
+                            triangle.uv = Some([uvs[a], uvs[b], uvs[c]]);
+                        }
+                    }
+                    triangles.push(triangle);
+                }
+                meshes.push(SimpleMesh {
+                    texture: texture.clone(),
+                    ..SimpleMesh::new(triangles)
+                });
+            }
+        }
+
+        let center = if meshes.is_empty() {
+            Point3::origin()
+        } else {
+            let (min, max) = meshes.iter().fold(
+                (Vector3::repeat(f32::MAX), Vector3::repeat(f32::MIN)),
+                |(min, max), mesh| {
+                    let bounds = &mesh.bounding_box;
+                    (min.inf(&bounds.min.xyz()), max.sup(&bounds.max.xyz()))
+                },
+            );
+            Point3::from((min + max) / 2.0)
+        };
+        let cameras = nodes
+            .iter()
+            .filter_map(|(node, transform)| node.camera().map(|c| camera(&c, transform, &center)))
+            .collect();
+        Ok(GltfScene { meshes, cameras })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A GLB with a textured triangle, scaled by one node and moved by its parent, and a camera.
+    /// Accessor 2 is two normals, one short of the triangle's positions.
+    fn glb(attributes: &str) -> Vec<u8> {
+        let mut image = vec![];
+        let mut encoder = png::Encoder::new(&mut image, 1, 1);
+        encoder.set_color(png::ColorType::RGB);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&[255, 0, 0])
+            .unwrap();
+        let mut bin = vec![];
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0].iter() {
+            bin.extend_from_slice(&value.to_le_bytes());
+        }
+        for _ in 0..6 {
+            bin.extend_from_slice(&0.5f32.to_le_bytes());
+        }
+        bin.extend_from_slice(&image);
+        let json = format!(
+            r#"{{
+            "asset": {{"version": "2.0"}},
+            "scene": 0,
+            "scenes": [{{"nodes": [0, 2]}}],
+            "nodes": [
+                {{"translation": [0, 0, -1], "children": [1]}},
+                {{"mesh": 0, "scale": [2, 2, 2]}},
+                {{"camera": 0, "translation": [0, 0, 5]}}
+            ],
+            "cameras": [{{"type": "perspective", "perspective": {{"yfov": 0.8, "znear": 0.1, "zfar": 50}}}}],
+            "meshes": [{{"primitives": [{{"attributes": {{{}}}, "material": 0}}]}}],
+            "materials": [{{"pbrMetallicRoughness": {{"baseColorFactor": [1, 0.5, 0.5, 1], "baseColorTexture": {{"index": 0}}}}}}],
+            "textures": [{{"source": 0}}],
+            "images": [{{"bufferView": 2, "mimeType": "image/png"}}],
+            "accessors": [
+                {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0]}},
+                {{"bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2"}},
+                {{"bufferView": 0, "componentType": 5126, "count": 2, "type": "VEC3"}}
+            ],
+            "bufferViews": [
+                {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+                {{"buffer": 0, "byteOffset": 36, "byteLength": 24}},
+                {{"buffer": 0, "byteOffset": 60, "byteLength": {}}}
+            ],
+            "buffers": [{{"byteLength": {}}}]
+        }}"#,
+            attributes,
+            image.len(),
+            bin.len()
+        );
+        let mut json = json.into_bytes();
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+        let mut glb = b"glTF".to_vec();
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&(12 + 8 + json.len() as u32 + 8 + bin.len() as u32).to_le_bytes());
+        for (chunk, kind) in [(&json, b"JSON"), (&bin, b"BIN\0")].iter() {
+            glb.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            glb.extend_from_slice(*kind);
+            glb.extend_from_slice(chunk);
+        }
+        glb
+    }
+
+    #[test]
+    fn test_glb() {
+        let scene = GltfScene::from_slice(&glb(r#""POSITION": 0, "TEXCOORD_0": 1"#)).unwrap();
+        assert_eq!(scene.meshes.len(), 1);
+        let mesh = &scene.meshes[0];
+        let triangle = &mesh.triangles[0];
+        assert_eq!(triangle.v2, Vector4::new(2.0, 0.0, -1.0, 1.0)); // Scaled, then moved
+        assert_eq!(triangle.color, (255, 188, 188)); // The base color factor, in sRGB
+        let texture = mesh.texture.as_ref().unwrap();
+        assert_eq!(texture.sample(triangle.uv.unwrap()[0]), (255, 0, 0));
+
+        assert_eq!(scene.cameras.len(), 1);
+        let camera = &scene.cameras[0];
+        assert_eq!(camera.position, Point3::new(0.0, 0.0, 5.0));
+        assert_eq!(camera.target, Point3::new(0.0, 0.0, -1.0)); // As far as the scene's center
+        assert_eq!((camera.fov, camera.near, camera.far), (0.8, 0.1, 50.0));
+
+        let short = glb(r#""POSITION": 0, "NORMAL": 2"#);
+        assert!(GltfScene::from_slice(&short).is_err());
+    }
+
+    #[test]
+    fn test_srgb() {
+        assert_eq!(srgb(0.0), 0);
+        assert_eq!(srgb(0.5), 188);
+        assert_eq!(srgb(1.0), 255);
+    }
+}
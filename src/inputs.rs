@@ -1,10 +1,19 @@
-use crate::context::Context;
-use crate::geometry::{SimpleMesh, ToSimpleMesh, ToSimpleMeshWithMaterial};
 use clap::{App, Arg, ArgMatches, SubCommand};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs::OpenOptions;
+use std::io::{BufReader, Write};
 use std::path::Path;
 
+use nalgebra::{Point3, Vector3};
+use sloth::{
+    Background, Camera, CastWriter, ColorDepth, Culling, GifWriter, GltfScene, Light, Off,
+    PixelMode, Ply, Projection, Ramp, Shading, SimpleMesh, Stl, ThreeMf, ToSimpleMesh,
+    ToSimpleMeshWithMaterial, Turntable, Winding, STL_COLOR,
+};
+
+use crate::scene_file::{SceneFile, TurntableEntry};
+
 pub fn cli_matches<'a>() -> ArgMatches<'a> {
     commands_for_subcommands(
         App::new("Sloth")
@@ -34,6 +43,73 @@ pub fn cli_matches<'a>() -> ArgMatches<'a> {
                             .short("h")
                             .help("Sets the height of the image to generate")
                             .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("png")
+                            .long("png")
+                            .help("Writes the render to a PNG file in full color, one image pixel per pixel")
+                            .takes_value(true)
+                            .conflicts_with("frame count"),
+                    )
+                    .arg(
+                        Arg::with_name("depth")
+                            .long("depth")
+                            .help("Writes the render's depth map to a PNG file, nearer pixels brighter")
+                            .takes_value(true)
+                            .conflicts_with("frame count"),
+                    )
+                    .arg(
+                        Arg::with_name("text png")
+                            .long("text-png")
+                            .help("Writes the render's glyphs to a PNG file, drawn with a built-in bitmap font")
+                            .takes_value(true)
+                            .conflicts_with_all(&["frame count", "png", "depth"]),
+                    )
+                    .arg(
+                        Arg::with_name("svg")
+                            .long("svg")
+                            .help("Writes the render's glyphs to an SVG file as text")
+                            .takes_value(true)
+                            .conflicts_with_all(&["frame count", "png", "depth"]),
+                    )
+                    .arg(
+                        Arg::with_name("gif")
+                            .long("gif")
+                            .help("Writes one turn of the turntable to an animated GIF file, drawn with a built-in bitmap font")
+                            .takes_value(true)
+                            .conflicts_with_all(&["frame count", "png", "depth", "text png", "svg"]),
+                    )
+                    .arg(
+                        Arg::with_name("cast")
+                            .long("cast")
+                            .help("Writes one turn of the turntable to an asciicast file, for asciinema and other terminal players")
+                            .takes_value(true)
+                            .conflicts_with_all(&["frame count", "png", "depth", "text png", "svg", "gif"]),
+                    )
+                    .arg(
+                        Arg::with_name("frames")
+                            .long("frames")
+                            .help("How many frames one turn takes in a GIF or cast [default: 60]")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("delay")
+                            .long("delay")
+                            .help("How long a GIF or cast shows each frame for, in milliseconds [default: 50]")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("gif loops")
+                            .long("loops")
+                            .help("How many times the GIF plays, 0 for forever [default: 0]")
+                            .takes_value(true)
+                            .requires("gif"),
+                    )
+                    .arg(
+                        Arg::with_name("gif pixels")
+                            .long("gif-pixels")
+                            .help("Draws the GIF in full color, one image pixel per pixel, instead of glyphs")
+                            .requires("gif"),
                     ),
             ))
             .arg(
@@ -48,7 +124,9 @@ pub fn cli_matches<'a>() -> ArgMatches<'a> {
 }
 
 fn commands_for_subcommands<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
-    command_flag_color(command_rotates(app))
+    let app = command_flag_color(command_rotates(app));
+    let app = command_camera(command_culling(app));
+    command_shading(command_lights(app))
 }
 
 fn command_flag_color<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
@@ -57,6 +135,53 @@ fn command_flag_color<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
             .short("b")
             .help("Flags the rasterizer to render without color"),
     )
+    .arg(
+        Arg::with_name("color depth")
+            .long("color-depth")
+            .help(
+                "Sets the colors the terminal can show, guessed from COLORTERM and TERM by default",
+            )
+            .possible_values(&["auto", "truecolor", "256", "16", "mono"])
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("background")
+            .long("background")
+            .help(
+                "Sets the background as a #rrggbb color, a #top:#bottom gradient, or none to keep \
+                 the terminal's own",
+            )
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("stl color")
+            .long("stl-color")
+            .help("Sets the #rrggbb color of STL facets that don't have one of their own")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("color")
+            .long("color")
+            .help("Paints one input file a #rrggbb color, given as file=#rrggbb")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+    )
+    .arg(
+        Arg::with_name("palette")
+            .long("palette")
+            .help("Paints every input file and OBJ group a different color"),
+    )
+    .arg(
+        Arg::with_name("stats")
+            .long("stats")
+            .help("Prints how many bytes a frame took to draw on average, after quitting"),
+    )
+    .arg(
+        Arg::with_name("dither")
+            .long("dither")
+            .help("Dithers colors when the terminal only has 256 or 16 of them"),
+    )
 }
 
 fn command_rotates<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
@@ -81,6 +206,175 @@ fn command_rotates<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
             .help("Sets the object's static Z rotation (in radians)")
             .takes_value(true),
     )
+    .arg(
+        Arg::with_name("speed")
+            .long("speed")
+            .help("Sets how fast the object spins (in radians per second, 1 by default)")
+            .takes_value(true)
+            .allow_hyphen_values(true),
+    )
+    .arg(
+        Arg::with_name("axis")
+            .long("axis")
+            .help("Sets the axis the object spins around as x,y,z (the vertical axis by default)")
+            .takes_value(true)
+            .allow_hyphen_values(true),
+    )
+    .arg(
+        Arg::with_name("spin")
+            .long("spin")
+            .help(
+                "Sets the object's angular velocity as x,y,z radians per second around each axis, \
+                 instead of --speed and --axis",
+            )
+            .takes_value(true)
+            .allow_hyphen_values(true),
+    )
+    .arg(
+        Arg::with_name("oscillate")
+            .long("oscillate")
+            .help("Swings the object back and forth by this many degrees either side, instead of spinning")
+            .takes_value(true),
+    )
+}
+
+fn command_camera<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name("camera")
+            .long("camera")
+            .help("Sets the camera's position as x,y,z (defaults to fitting the scene in view)")
+            .takes_value(true)
+            .allow_hyphen_values(true),
+    )
+    .arg(
+        Arg::with_name("look at")
+            .long("look-at")
+            .help("Sets the point the camera looks at as x,y,z")
+            .takes_value(true)
+            .allow_hyphen_values(true),
+    )
+    .arg(
+        Arg::with_name("up")
+            .long("up")
+            .help("Sets the camera's up direction as x,y,z")
+            .takes_value(true)
+            .allow_hyphen_values(true),
+    )
+    .arg(
+        Arg::with_name("fov")
+            .long("fov")
+            .help("Sets the camera's vertical field of view (in degrees)")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("near")
+            .long("near")
+            .help("Sets the distance to the camera's near clipping plane")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("far")
+            .long("far")
+            .help("Sets the distance to the camera's far clipping plane")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("orthographic")
+            .long("orthographic")
+            .help("Renders with an orthographic projection instead of perspective"),
+    )
+}
+
+fn command_culling<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name("cull")
+            .long("cull")
+            .help("Skips rasterizing the back or front faces of triangles")
+            .possible_values(&["none", "back", "front"])
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("winding")
+            .long("winding")
+            .help("Sets which vertex order front faces have (counter-clockwise by default)")
+            .possible_values(&["ccw", "cw"])
+            .takes_value(true),
+    )
+}
+
+fn command_shading<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name("shading")
+            .long("shading")
+            .help("Sets how shades are blended across triangles (phong by default)")
+            .possible_values(&["flat", "gouraud", "phong"])
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("smooth angle")
+            .long("smooth-angle")
+            .help("Sets the angle (in degrees) past which edges stay sharp, for models without normals")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("pixel mode")
+            .long("pixel-mode")
+            .help(
+                "Sets how pixels are drawn: as glyphs two cells wide (the default), as colored \
+                 half blocks, two to a cell, or as Braille dots, eight to a cell",
+            )
+            .possible_values(&["glyph", "half-block", "braille"])
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("ramp")
+            .long("ramp")
+            .help(
+                "Sets the glyphs shades are drawn with, from dimmest to brightest, or one of the \
+                 standard, bourke, blocks, digits and dense presets",
+            )
+            .takes_value(true)
+            .allow_hyphen_values(true),
+    )
+    .arg(
+        Arg::with_name("gamma")
+            .long("gamma")
+            .help("Bends shades before picking their glyph, above 1 brightens them")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("contrast")
+            .long("contrast")
+            .help("Stretches shades around the middle gray before picking their glyph")
+            .takes_value(true),
+    )
+}
+
+fn command_lights<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name("light")
+            .long("light")
+            .help(
+                "Adds a light as directional:x,y,z or point:x,y,z, optionally followed by \
+                 :color (as #rrggbb) and :intensity. Replaces the default headlight",
+            )
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .allow_hyphen_values(true),
+    )
+    .arg(
+        Arg::with_name("ambient")
+            .long("ambient")
+            .help("Adds ambient light with an intensity, optionally followed by :color")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("scene")
+            .long("scene")
+            .help("Reads lights and other settings from a TOML scene file")
+            .takes_value(true),
+    )
 }
 
 pub fn to_meshes(models: Vec<tobj::Model>, materials: Vec<tobj::Material>) -> Vec<SimpleMesh> {
@@ -91,9 +385,31 @@ pub fn to_meshes(models: Vec<tobj::Model>, materials: Vec<tobj::Material>) -> Ve
     meshes
 }
 
-pub fn match_meshes(matches: &ArgMatches) -> Result<Vec<SimpleMesh>, Box<dyn Error>> {
-    let mut mesh_queue: Vec<SimpleMesh> = vec![];
-    for slice in matches.value_of("input filename(s)").unwrap().split(' ') {
+/// The matches for the options that color meshes, which can also be given after `image`
+fn mesh_matches<'a>(matches: &'a ArgMatches<'a>, name: &str) -> &'a ArgMatches<'a> {
+    match matches.subcommand_matches("image") {
+        Some(image) if image.is_present(name) => image,
+        _ => matches,
+    }
+}
+
+/// The color of STL facets without one
+fn match_stl_color(matches: &ArgMatches) -> Result<(u8, u8, u8), Box<dyn Error>> {
+    match mesh_matches(matches, "stl color").value_of("stl color") {
+        Some(value) => parse_rgb(value),
+        None => Ok(STL_COLOR),
+    }
+}
+
+/// The meshes of every input file, and the cameras the files come with
+pub fn match_meshes(
+    matches: &ArgMatches,
+) -> Result<(Vec<SimpleMesh>, Vec<Camera>), Box<dyn Error>> {
+    let mut cameras = vec![];
+    let stl_color = match_stl_color(matches)?;
+    let mut inputs = vec![];
+    let files = matches.values_of("input filename(s)").unwrap();
+    for slice in files.flat_map(|files| files.split(' ')) {
         let error = |s: &str, e: &str| -> Result<Vec<SimpleMesh>, Box<dyn Error>> {
             Err(format!("filename: [{}] couldn't load, {}. {}", slice, s, e).into())
         };
@@ -104,62 +420,473 @@ pub fn match_meshes(matches: &ArgMatches) -> Result<Vec<SimpleMesh>, Box<dyn Err
             Some(ext) => match ext.to_str() {
                 None => error("couldn't parse filename extension", ""),
                 Some(extstr) => match &*extstr.to_lowercase() {
-                    "obj" => match tobj::load_obj(&path, true) {
+                    "obj" => match tobj::load_obj(path, true) {
                         Err(e) => error("tobj couldnt load/parse OBJ", &e.to_string()),
                         Ok(present) => Ok(to_meshes(present.0, present.1)),
                     },
-                    "stl" => match OpenOptions::new().read(true).open(&path) {
+                    "stl" => match OpenOptions::new().read(true).open(path) {
                         Err(e) => error("STL load failed", &e.to_string()),
-                        Ok(mut file) => match stl_io::read_stl(&mut file) {
-                            Err(e) => error("stl_io couldnt parse STL", &e.to_string()),
-                            Ok(stlio_mesh) => Ok(vec![stlio_mesh.to_simple_mesh()]),
+                        Ok(file) => match Stl::read(BufReader::new(file)) {
+                            Err(e) => error("couldnt parse STL", &e.to_string()),
+                            Ok(stl) => Ok(vec![stl.to_simple_mesh_with_color(stl_color)]),
+                        },
+                    },
+                    "ply" => match OpenOptions::new().read(true).open(path) {
+                        Err(e) => error("PLY load failed", &e.to_string()),
+                        Ok(file) => match Ply::read(BufReader::new(file)) {
+                            Err(e) => error("couldnt parse PLY", &e.to_string()),
+                            Ok(ply) => Ok(vec![ply.to_simple_mesh()]),
+                        },
+                    },
+                    "off" => match OpenOptions::new().read(true).open(path) {
+                        Err(e) => error("OFF load failed", &e.to_string()),
+                        Ok(file) => match Off::read(BufReader::new(file)) {
+                            Err(e) => error("couldnt parse OFF", &e.to_string()),
+                            Ok(off) => Ok(vec![off.to_simple_mesh()]),
+                        },
+                    },
+                    "3mf" => match OpenOptions::new().read(true).open(path) {
+                        Err(e) => error("3MF load failed", &e.to_string()),
+                        Ok(file) => match ThreeMf::read(BufReader::new(file)) {
+                            Err(e) => error("couldnt parse 3MF", &e.to_string()),
+                            Ok(model) => Ok(model.meshes),
                         },
                     },
+                    "gltf" | "glb" => match GltfScene::load(path) {
+                        Err(e) => error("couldnt load glTF", &e.to_string()),
+                        Ok(scene) => {
+                            cameras.extend(scene.cameras);
+                            Ok(scene.meshes)
+                        }
+                    },
                     _ => error("unknown filename extension", ""),
                 },
             },
         };
-        mesh_queue.append(&mut meshes.unwrap());
+        inputs.push((slice, meshes?));
+    }
+    let colors = mesh_matches(matches, "color").values_of("color");
+    let palette = mesh_matches(matches, "palette").is_present("palette");
+    color_inputs(&mut inputs, colors.into_iter().flatten(), palette)?;
+    let mesh_queue: Vec<SimpleMesh> = inputs.into_iter().flat_map(|(_, meshes)| meshes).collect();
+    Ok((mesh_queue, cameras))
+}
+
+/// Paints the meshes of every input file a color of their own with `palette`, then the files
+/// `colors` names (as file=#rrggbb, by path or file name) theirs
+fn color_inputs<'a>(
+    inputs: &mut [(&str, Vec<SimpleMesh>)],
+    colors: impl Iterator<Item = &'a str>,
+    palette: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut files = HashMap::new();
+    for value in colors {
+        match value.rsplit_once('=') {
+            Some((file, color)) => {
+                files.insert(file, parse_rgb(color)?); // The last one given wins
+            }
+            None => return Err(format!("color: expected file=#rrggbb, got [{}]", value).into()),
+        }
+    }
+    let mut used = HashSet::new();
+    let mut id = 0;
+    for (slice, meshes) in inputs.iter_mut() {
+        if palette {
+            for mesh in meshes.iter_mut() {
+                mesh.recolor(sloth::palette(id));
+                id += 1;
+            }
+        }
+        let file_name = Path::new(slice).file_name().and_then(|name| name.to_str());
+        let key = Some(*slice)
+            .filter(|slice| files.contains_key(slice))
+            .or_else(|| file_name.filter(|name| files.contains_key(name)));
+        if let Some(key) = key {
+            for mesh in meshes.iter_mut() {
+                mesh.recolor(files[key]);
+            }
+            used.insert(key);
+        }
     }
-    Ok(mesh_queue)
+    if let Some(file) = files.keys().find(|file| !used.contains(*file)) {
+        return Err(format!("color: [{}] isn't one of the input files", file).into());
+    }
+    Ok(())
 }
 
-pub fn match_turntable(matches: &ArgMatches) -> Result<(f32, f32, f32, f32), Box<dyn Error>> {
-    let mut turntable = (0.0, 0.0, 0.0, 0.0);
-    if let Some(x) = matches.value_of("x") {
-        turntable.0 = x.parse()?;
+pub fn match_turntable(matches: &ArgMatches) -> Result<Turntable, Box<dyn Error>> {
+    let mut turntable = Turntable::default();
+    let static_rotation = [("x", 0), ("y", 1), ("z", 2)];
+    for (name, axis) in static_rotation.iter() {
+        if let Some(angle) = matches.value_of(name) {
+            turntable.orientation[*axis] = angle.parse()?;
+        }
+    }
+    let mut spin = match matches.value_of("scene") {
+        Some(path) => SceneFile::load(Path::new(path))?
+            .turntable
+            .unwrap_or_default(),
+        None => TurntableEntry::default(),
+    };
+    if let Some(speed) = matches.value_of("speed") {
+        spin.speed = Some(speed.parse()?);
     }
-    if let Some(y) = matches.value_of("y") {
-        turntable.1 = y.parse()?;
+    if let Some(axis) = matches.value_of("axis") {
+        spin.axis = Some(parse_vector(axis)?.into());
     }
-    if let Some(z) = matches.value_of("z") {
-        turntable.2 = z.parse()?;
+    if let Some(velocity) = matches.value_of("spin") {
+        spin.spin = Some(parse_vector(velocity)?.into());
     }
-    if let Some(s) = matches.value_of("speed") {
-        turntable.3 = s.parse()?;
-    } else {
-        turntable.3 = 1.0; // No speed defined -> 1.0 rad/s
+    if let Some(degrees) = matches.value_of("oscillate") {
+        spin.oscillate = Some(degrees.parse()?);
     }
-    turntable.1 += std::f32::consts::PI; // All models for some reason are backwards, this fixes that
+    turntable.velocity = match spin.spin {
+        Some(velocity) => Vector3::from(velocity),
+        None => {
+            let axis = Vector3::from(spin.axis.unwrap_or([0.0, 1.0, 0.0]));
+            let axis = axis
+                .try_normalize(f32::EPSILON)
+                .ok_or("axis: can't be zero")?;
+            axis * spin.speed.unwrap_or(1.0) // No speed defined -> 1.0 rad/s
+        }
+    };
+    turntable.oscillation = spin.oscillate.map(f32::to_radians);
     Ok(turntable)
 }
 
+fn parse_vector(value: &str) -> Result<Vector3<f32>, Box<dyn Error>> {
+    let axes = value
+        .split(',')
+        .map(|axis| axis.trim().parse())
+        .collect::<Result<Vec<f32>, _>>()?;
+    match axes[..] {
+        [x, y, z] => Ok(Vector3::new(x, y, z)),
+        _ => Err(format!("expected a vector as x,y,z, got [{}]", value).into()),
+    }
+}
+
+/// Parses colors written as #rrggbb (the # is optional) into RGB from 0 to 1
+pub fn parse_color(value: &str) -> Result<Vector3<f32>, Box<dyn Error>> {
+    let hex = value.trim_start_matches('#');
+    let channel = |at: usize| -> Result<f32, Box<dyn Error>> {
+        let digits = hex.get(at..at + 2).ok_or("")?;
+        Ok(u8::from_str_radix(digits, 16)? as f32 / 255.0)
+    };
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Ok(r), Ok(g), Ok(b)) => Ok(Vector3::new(r, g, b)),
+        _ => Err(format!("expected a color as #rrggbb, got [{}]", value).into()),
+    }
+}
+
+/// A `#rrggbb` color as bytes
+pub fn parse_rgb(value: &str) -> Result<(u8, u8, u8), Box<dyn Error>> {
+    let color = parse_color(value)? * 255.0;
+    Ok((
+        color.x.round() as u8,
+        color.y.round() as u8,
+        color.z.round() as u8,
+    ))
+}
+
+fn parse_light(value: &str) -> Result<Light, Box<dyn Error>> {
+    let mut parts = value.split(':');
+    let kind = parts.next().unwrap_or_default();
+    let vector = parse_vector(parts.next().unwrap_or_default())?;
+    let color = match parts.next() {
+        Some(color) => parse_color(color)?,
+        None => Vector3::new(1.0, 1.0, 1.0),
+    };
+    let intensity = match parts.next() {
+        Some(intensity) => intensity.parse()?,
+        None => 1.0,
+    };
+    match kind {
+        "directional" => Ok(Light::Directional {
+            direction: vector,
+            color,
+            intensity,
+        }),
+        "point" => Ok(Light::Point {
+            position: Point3::from(vector),
+            color,
+            intensity,
+        }),
+        _ => Err(format!("expected a directional or point light, got [{}]", value).into()),
+    }
+}
+
+pub fn match_lights(matches: &ArgMatches) -> Result<Vec<Light>, Box<dyn Error>> {
+    let mut lights = match matches.value_of("scene") {
+        Some(path) => SceneFile::load(Path::new(path))?.lights()?,
+        None => vec![],
+    };
+    if let Some(values) = matches.values_of("light") {
+        for value in values {
+            lights.push(parse_light(value)?);
+        }
+    }
+    if let Some(ambient) = matches.value_of("ambient") {
+        let mut parts = ambient.splitn(2, ':');
+        lights.push(Light::Ambient {
+            intensity: parts.next().unwrap_or_default().parse()?,
+            color: match parts.next() {
+                Some(color) => parse_color(color)?,
+                None => Vector3::new(1.0, 1.0, 1.0),
+            },
+        });
+    }
+    Ok(lights)
+}
+
+/// The camera the options ask for, starting from the one the model file has, if any
+pub fn match_camera(
+    matches: &ArgMatches,
+    meshes: &[SimpleMesh],
+    from_file: Option<&Camera>,
+) -> Result<Camera, Box<dyn Error>> {
+    let mut camera = from_file.cloned().unwrap_or_else(|| Camera::fit(meshes));
+    if let Some(fov) = matches.value_of("fov") {
+        let fov: f32 = fov.parse()?;
+        if !(fov > 0.0 && fov < 180.0) {
+            return Err("the camera's field of view must be between 0 and 180 degrees".into());
+        }
+        camera.fov = fov.to_radians();
+        if from_file.is_none() {
+            camera.frame(meshes);
+        }
+    }
+    if let Some(position) = matches.value_of("camera") {
+        camera.position = Point3::from(parse_vector(position)?);
+        camera.far += camera.position.coords.norm();
+    }
+    if let Some(target) = matches.value_of("look at") {
+        camera.target = Point3::from(parse_vector(target)?);
+    }
+    if let Some(up) = matches.value_of("up") {
+        camera.up = parse_vector(up)?;
+    }
+    if let Some(near) = matches.value_of("near") {
+        camera.near = near.parse()?;
+    }
+    if let Some(far) = matches.value_of("far") {
+        camera.far = far.parse()?;
+    }
+    if matches.is_present("orthographic") {
+        camera.projection = Projection::Orthographic;
+    }
+    if camera.near <= 0.0 || camera.far <= camera.near {
+        return Err(
+            "the camera's near plane must be positive and closer than its far plane".into(),
+        );
+    }
+    Ok(camera)
+}
+
+pub fn match_culling(matches: &ArgMatches) -> (Culling, Winding) {
+    let culling = match matches.value_of("cull") {
+        Some("back") => Culling::Back,
+        Some("front") => Culling::Front,
+        _ => Culling::None,
+    };
+    let winding = match matches.value_of("winding") {
+        Some("cw") => Winding::Clockwise,
+        _ => Winding::CounterClockwise,
+    };
+    (culling, winding)
+}
+
+pub fn match_shading(matches: &ArgMatches) -> Result<(Shading, f32), Box<dyn Error>> {
+    let shading = match matches.value_of("shading") {
+        Some("flat") => Shading::Flat,
+        Some("gouraud") => Shading::Gouraud,
+        _ => Shading::Phong,
+    };
+    let smooth_angle = match matches.value_of("smooth angle") {
+        Some(angle) => angle.parse::<f32>()?.to_radians(),
+        None => 60f32.to_radians(),
+    };
+    Ok((shading, smooth_angle))
+}
+
+pub fn match_pixel_mode(matches: &ArgMatches) -> Option<PixelMode> {
+    match matches.value_of("pixel mode") {
+        Some("glyph") => Some(PixelMode::Glyph),
+        Some("half-block") => Some(PixelMode::HalfBlock),
+        Some("braille") => Some(PixelMode::Braille),
+        _ => None,
+    }
+}
+
+/// The glyph ramp to draw with, if any of the ramp options are set
+pub fn match_ramp(matches: &ArgMatches) -> Result<Option<Ramp>, Box<dyn Error>> {
+    if !["ramp", "gamma", "contrast"]
+        .iter()
+        .any(|option| matches.is_present(option))
+    {
+        return Ok(None);
+    }
+    let mut ramp = match matches.value_of("ramp") {
+        Some(ramp) => Ramp::preset(ramp).unwrap_or_else(|| Ramp::new(ramp)),
+        None => Ramp::default(),
+    };
+    if ramp.glyphs.is_empty() {
+        return Err("ramp: needs at least one glyph".into());
+    }
+    if let Some(gamma) = matches.value_of("gamma") {
+        ramp.gamma = gamma.parse()?;
+        if ramp.gamma <= 0.0 {
+            return Err("gamma: has to be above 0".into());
+        }
+    }
+    if let Some(contrast) = matches.value_of("contrast") {
+        ramp.contrast = contrast.parse()?;
+    }
+    Ok(Some(ramp))
+}
+
 pub fn match_image_mode(matches: &ArgMatches) -> bool {
     matches.is_present("image")
 }
 
+pub fn match_background(matches: &ArgMatches) -> Result<Option<Background>, Box<dyn Error>> {
+    let value = match matches.value_of("background") {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let background = match value.split(':').collect::<Vec<_>>().as_slice() {
+        ["none"] | ["transparent"] => Background::Transparent,
+        [color] => Background::Solid(parse_rgb(color)?),
+        [top, bottom] => Background::Gradient(parse_rgb(top)?, parse_rgb(bottom)?),
+        _ => return Err(format!("background: expected a color or two, got [{}]", value).into()),
+    };
+    Ok(Some(background))
+}
+
+/// The color depth to flush with, `None` when it's left to detection
+pub fn match_color_depth(matches: &ArgMatches) -> Option<ColorDepth> {
+    match matches.value_of("color depth") {
+        Some("truecolor") => Some(ColorDepth::TrueColor),
+        Some("256") => Some(ColorDepth::Ansi256),
+        Some("16") => Some(ColorDepth::Ansi16),
+        Some("mono") => Some(ColorDepth::Mono),
+        _ => None,
+    }
+}
+
 pub fn match_no_color_mode(matches: &ArgMatches) -> bool {
     matches.is_present("no color")
 }
 
-pub fn match_dimensions(context: &mut Context, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+/// How many frames `-j` renders for the web, if it's given
+pub fn match_webify(matches: &ArgMatches) -> Result<Option<usize>, Box<dyn Error>> {
+    let frames: usize = match matches.value_of("frame count") {
+        Some(frames) => frames.parse()?,
+        None => return Ok(None),
+    };
+    if frames == 0 {
+        return Err("webify: needs at least one frame".into());
+    }
+    Ok(Some(frames))
+}
+
+/// How many frames a recording of one turn has, and how many seconds apart they are
+pub fn match_recording(matches: &ArgMatches) -> Result<(usize, f32), Box<dyn Error>> {
+    let frames: usize = matches.value_of("frames").unwrap_or("60").parse()?;
+    if frames == 0 {
+        return Err("frames: needs at least one".into());
+    }
+    let delay: u32 = matches.value_of("delay").unwrap_or("50").parse()?;
+    Ok((frames, delay as f32 / 1000.0))
+}
+
+/// The GIF to write instead of printing, to the file `create` opens
+pub fn match_gif<W: Write>(
+    matches: &ArgMatches,
+    delay: f32,
+    create: impl FnOnce(&Path) -> Result<W, Box<dyn Error>>,
+) -> Result<Option<GifWriter<W>>, Box<dyn Error>> {
+    let path = match matches.value_of("gif") {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let loops: u16 = matches.value_of("gif loops").unwrap_or("0").parse()?;
+    let mut gif = GifWriter::new(create(Path::new(path))?);
+    gif.delay = (delay * 100.0).round().min(u16::MAX as f32) as u16; // GIFs count in hundredths of a second
+    gif.plays = if loops == 0 { None } else { Some(loops) };
+    gif.pixels = matches.is_present("gif pixels");
+    Ok(Some(gif))
+}
+
+/// The asciicast to write instead of printing, `width` by `height` cells, to the file `create` opens
+pub fn match_cast<W: Write>(
+    matches: &ArgMatches,
+    (width, height): (usize, usize),
+    create: impl FnOnce(&Path) -> Result<W, Box<dyn Error>>,
+) -> Result<Option<CastWriter<W>>, Box<dyn Error>> {
+    match matches.value_of("cast") {
+        Some(path) => Ok(Some(CastWriter::new(
+            create(Path::new(path))?,
+            width,
+            height,
+        ))),
+        None => Ok(None),
+    }
+}
+
+pub fn match_dimensions(matches: &ArgMatches) -> Result<(usize, usize), Box<dyn Error>> {
+    let mut dimensions = (0, 0);
     if let Some(x) = matches.value_of("width") {
-        context.width = x.parse()?;
+        dimensions.0 = x.parse()?;
         if let Some(y) = matches.value_of("height") {
-            context.height = y.parse()?;
+            dimensions.1 = y.parse()?;
         } else {
-            context.height = context.width;
+            dimensions.1 = dimensions.0;
         }
     }
-    Ok(())
+    Ok(dimensions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sloth::Triangle;
+
+    fn inputs<'a>(files: &[&'a str]) -> Vec<(&'a str, Vec<SimpleMesh>)> {
+        let mesh = || SimpleMesh::new(vec![Triangle::default()]);
+        files
+            .iter()
+            .map(|file| (*file, vec![mesh(), mesh()]))
+            .collect()
+    }
+
+    fn color(inputs: &[(&str, Vec<SimpleMesh>)], file: usize, mesh: usize) -> (u8, u8, u8) {
+        inputs[file].1[mesh].triangles[0].color
+    }
+
+    #[test]
+    fn test_color_inputs() {
+        let mut files = inputs(&["models/cube.stl", "parts/part.stl"]);
+        let colors = [
+            "models/cube.stl=#ff0000",
+            "part.stl=#00ff00",
+            "part.stl=#0000ff",
+        ];
+        color_inputs(&mut files, colors.iter().copied(), false).unwrap();
+        assert_eq!(color(&files, 0, 1), (255, 0, 0)); // By path
+        assert_eq!(color(&files, 1, 0), (0, 0, 255)); // By file name, the last one given
+
+        let mut files = inputs(&["cube.stl"]);
+        let error = color_inputs(&mut files, ["prat.stl=#ff0000"].iter().copied(), false);
+        assert!(error.unwrap_err().to_string().contains("prat.stl"));
+        assert!(color_inputs(&mut files, ["#ff0000"].iter().copied(), false).is_err());
+    }
+
+    #[test]
+    fn test_palette() {
+        let mut files = inputs(&["cube.stl", "part.stl"]);
+        color_inputs(&mut files, ["cube.stl=#ffffff"].iter().copied(), true).unwrap();
+        assert_eq!(color(&files, 0, 0), (255, 255, 255)); // Given colors win
+        assert_eq!(color(&files, 1, 0), sloth::palette(2)); // Counting every mesh before
+        assert_eq!(color(&files, 1, 1), sloth::palette(3));
+    }
 }
@@ -0,0 +1,57 @@
+//! sloth is a software rasterizer that renders triangle meshes into a grid of colored glyphs.
+//!
+//! The library never touches the terminal by itself: a [`Renderer`] draws a [`Scene`] into an
+//! in-memory [`Context`], and it's up to the caller to [`Context::flush`] it into a writer.
+
+pub mod animation;
+pub use animation::*;
+
+pub mod camera;
+pub use camera::*;
+
+pub mod clipping;
+pub use clipping::*;
+
+pub mod color;
+pub use color::*;
+
+pub mod context;
+pub use context::*;
+
+pub mod export;
+pub use export::*;
+
+pub mod font;
+
+pub mod geometry;
+pub use geometry::*;
+
+pub mod gltf_scene;
+pub use gltf_scene::*;
+
+pub mod lighting;
+pub use lighting::*;
+
+pub mod off;
+pub use off::*;
+
+pub mod ply;
+pub use ply::*;
+
+pub mod rasterizer;
+pub use rasterizer::*;
+
+pub mod renderer;
+pub use renderer::*;
+
+pub mod shader;
+pub use shader::*;
+
+pub mod stl;
+pub use stl::*;
+
+pub mod texture;
+pub use texture::*;
+
+pub mod threemf;
+pub use threemf::*;
@@ -0,0 +1,233 @@
+use nalgebra::{Matrix4, Point3, Vector3};
+
+/// A light in the scene. Colors are linear RGB from 0 to 1, scaled by `intensity`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Light {
+    /// Light shining evenly onto every surface, from every direction
+    Ambient { color: Vector3<f32>, intensity: f32 },
+    /// Light travelling along `direction`, like sunlight
+    Directional {
+        direction: Vector3<f32>,
+        color: Vector3<f32>,
+        intensity: f32,
+    },
+    /// Light shining out of `position` in every direction. It doesn't fall off with distance
+    Point {
+        position: Point3<f32>,
+        color: Vector3<f32>,
+        intensity: f32,
+    },
+}
+
+impl Light {
+    /// A white light shining straight down the view axis, used when a scene has no lights
+    pub fn headlight() -> Light {
+        Light::Directional {
+            direction: -Vector3::z(),
+            color: Vector3::new(1.0, 1.0, 1.0),
+            intensity: 1.0,
+        }
+    }
+    /// Moves the light into the space `transform` leads to
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Light {
+        match self.clone() {
+            Light::Directional {
+                direction,
+                color,
+                intensity,
+            } => Light::Directional {
+                direction: transform.transform_vector(&direction),
+                color,
+                intensity,
+            },
+            Light::Point {
+                position,
+                color,
+                intensity,
+            } => Light::Point {
+                position: transform.transform_point(&position),
+                color,
+                intensity,
+            },
+            ambient => ambient,
+        }
+    }
+}
+
+/// How shiny a surface is. `color` tints its Blinn-Phong highlights, `shininess` tightens them.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Specular {
+    pub color: Vector3<f32>,
+    pub shininess: f32,
+}
+
+impl Default for Specular {
+    fn default() -> Self {
+        Self {
+            color: Vector3::zeros(),
+            shininess: 0.0,
+        }
+    }
+}
+
+/// The light reaching a point, split into what the surface's own color reflects (`diffuse`) and
+/// the highlights on top of it (`specular`, already tinted by the surface's specular color)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Illumination {
+    pub diffuse: Vector3<f32>,
+    pub specular: Vector3<f32>,
+}
+
+impl Illumination {
+    /// Lights `color` (0 to 255 per channel)
+    pub fn apply(&self, color: (u8, u8, u8)) -> Vector3<f32> {
+        let color = Vector3::new(color.0 as f32, color.1 as f32, color.2 as f32) / 255.0;
+        color.component_mul(&self.diffuse) + self.specular
+    }
+    /// Lights `color`, clamped back into 0 to 255 per channel
+    pub fn lit(&self, color: (u8, u8, u8)) -> (u8, u8, u8) {
+        let lit = self.apply(color) * 255.0;
+        let channel = |value: f32| value.clamp(0.0, 255.0) as u8;
+        (channel(lit.x), channel(lit.y), channel(lit.z))
+    }
+    /// How bright the light is overall, from 0 to 1
+    pub fn shade(&self) -> f32 {
+        let light = self.diffuse + self.specular;
+        (0.2126 * light.x + 0.7152 * light.y + 0.0722 * light.z).clamp(0.0, 1.0)
+    }
+    /// `color` tinted by the light, but as bright as it started out. Glyphs show how bright a
+    /// cell is, so its color only needs to show the light's hue
+    pub fn tint(&self, color: (u8, u8, u8)) -> (u8, u8, u8) {
+        let shade = self.shade();
+        if shade <= 0.0 {
+            return color;
+        }
+        let tinted = self.apply(color) / shade * 255.0;
+        let channel = |value: f32| value.clamp(0.0, 255.0) as u8;
+        (channel(tinted.x), channel(tinted.y), channel(tinted.z))
+    }
+}
+
+/// Lambert diffuse plus Blinn-Phong specular lighting for a surface at `position` facing `normal`,
+/// seen from the origin. Everything is in view space. Surfaces are lit on both sides.
+pub fn illuminate(
+    lights: &[Light],
+    position: &Vector3<f32>,
+    normal: &Vector3<f32>,
+    specular: &Specular,
+) -> Illumination {
+    let eye = (-position)
+        .try_normalize(f32::EPSILON)
+        .unwrap_or_else(Vector3::z);
+    let normal = if normal.dot(&eye) < 0.0 {
+        -normal // Seeing the back side
+    } else {
+        *normal
+    };
+    let mut illumination = Illumination {
+        diffuse: Vector3::zeros(),
+        specular: Vector3::zeros(),
+    };
+    for light in lights {
+        let (to_light, radiance) = match light {
+            Light::Ambient { color, intensity } => {
+                illumination.diffuse += color * *intensity;
+                continue;
+            }
+            Light::Directional {
+                direction,
+                color,
+                intensity,
+            } => (-direction, color * *intensity),
+            Light::Point {
+                position: light,
+                color,
+                intensity,
+            } => (light.coords - position, color * *intensity),
+        };
+        let to_light = match to_light.try_normalize(f32::EPSILON) {
+            Some(to_light) => to_light,
+            None => continue,
+        };
+        let lambert = normal.dot(&to_light);
+        if lambert <= 0.0 {
+            continue;
+        }
+        illumination.diffuse += radiance * lambert;
+        if specular.shininess > 0.0 && specular.color != Vector3::zeros() {
+            let half = (to_light + eye).normalize();
+            let highlight = normal.dot(&half).max(0.0).powf(specular.shininess);
+            illumination.specular += radiance.component_mul(&specular.color) * highlight;
+        }
+    }
+    illumination
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white() -> Vector3<f32> {
+        Vector3::new(1.0, 1.0, 1.0)
+    }
+
+    #[test]
+    fn test_lambert() {
+        let position = Vector3::new(0.0, 0.0, -5.0);
+        let normal = Vector3::z();
+        let lit = |direction: Vector3<f32>| {
+            let light = Light::Directional {
+                direction,
+                color: white(),
+                intensity: 1.0,
+            };
+            illuminate(&[light], &position, &normal, &Specular::default()).shade()
+        };
+        assert!((lit(-Vector3::z()) - 1.0).abs() < 1e-5);
+        assert!((lit(Vector3::new(0.0, -1.0, -1.0)) - 0.5f32.sqrt()).abs() < 1e-5);
+        assert_eq!(lit(Vector3::x()), 0.0);
+    }
+
+    #[test]
+    fn test_ambient_and_point() {
+        let lights = [
+            Light::Ambient {
+                color: Vector3::new(1.0, 0.0, 0.0),
+                intensity: 0.5,
+            },
+            Light::Point {
+                position: Point3::new(0.0, 0.0, 0.0),
+                color: white(),
+                intensity: 0.5,
+            },
+        ];
+        let position = Vector3::new(0.0, 0.0, -2.0);
+        let lit = illuminate(&lights, &position, &Vector3::z(), &Specular::default());
+        assert_eq!(lit.diffuse, Vector3::new(1.0, 0.5, 0.5));
+        assert_eq!(lit.tint((255, 255, 255)).0, 255);
+    }
+
+    #[test]
+    fn test_specular_highlight() {
+        let specular = Specular {
+            color: white(),
+            shininess: 32.0,
+        };
+        let light = [Light::headlight()];
+        let position = Vector3::new(0.0, 0.0, -5.0);
+        let head_on = illuminate(&light, &position, &Vector3::z(), &specular);
+        let tilted = Vector3::new(0.0, 1.0, 2.0).normalize();
+        let grazing = illuminate(&light, &position, &tilted, &specular);
+        assert!((head_on.specular.x - 1.0).abs() < 1e-5);
+        assert!(grazing.specular.x < 0.1);
+    }
+
+    #[test]
+    fn test_back_faces_are_lit() {
+        let light = [Light::headlight()];
+        let position = Vector3::new(0.0, 0.0, -5.0);
+        let front = illuminate(&light, &position, &Vector3::z(), &Specular::default());
+        let back = illuminate(&light, &position, &-Vector3::z(), &Specular::default());
+        assert_eq!(front, back);
+    }
+}
@@ -1,26 +1,55 @@
 use crossterm::{
     cursor,
-    event::{poll, read, Event, KeyCode, KeyEvent, KeyModifiers},
-    ExecutableCommand,
+    event::{poll, read, DisableMouseCapture, EnableMouseCapture, Event},
+    terminal, ExecutableCommand,
 };
 use std::error::Error;
 use std::f32;
-use std::io::{stdout, Write};
+use std::fs::File;
+use std::io::{stdout, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
-use nalgebra::Rotation3;
+use sloth::{
+    write_depth_png, write_png, write_svg, write_text_png, ColorDepth, PixelMode, Renderer, Scene,
+};
+
+mod controls;
+use controls::Controls;
+
+mod inputs;
+use inputs::*;
 
-pub mod context;
-pub use context::*;
+mod scene_file;
 
-pub mod geometry;
-pub use geometry::*;
+/// Holds the terminal in raw mode with the mouse captured and the cursor hidden, and puts it back
+/// the way it was when dropped, even when rendering fails
+struct TerminalGuard;
 
-pub mod rasterizer;
-pub use rasterizer::*;
+impl TerminalGuard {
+    fn start<W: Write>(out: &mut W) -> Result<TerminalGuard, Box<dyn Error>> {
+        terminal::enable_raw_mode()?;
+        let guard = TerminalGuard; // Restores raw mode if the rest fails
+        out.execute(cursor::Hide)?;
+        out.execute(EnableMouseCapture)?;
+        Ok(guard)
+    }
+}
 
-pub mod inputs;
-pub use inputs::*;
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut out = stdout();
+        let _ = out.execute(DisableMouseCapture);
+        let _ = out.execute(cursor::Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>, Box<dyn Error>> {
+    let file = File::create(path)
+        .map_err(|e| format!("output: [{}] couldn't create, {}", path.display(), e))?;
+    Ok(BufWriter::new(file))
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
     let matches = cli_matches(); // Read command line arguments
@@ -28,75 +57,193 @@ fn main() -> Result<(), Box<dyn Error>> {
     let fps_cap = 500.0;
     let target_frame_time = Duration::from_secs_f64(1.0 / fps_cap);
 
-    let mesh_queue: Vec<SimpleMesh> = match_meshes(&matches)?; // A list of meshes to render
+    let (meshes, cameras) = match_meshes(&matches)?;
+    let mut scene = Scene::new(meshes); // A list of meshes to render
+    scene.camera = match_camera(&matches, &scene.meshes, cameras.first())?;
+    scene.lights = match_lights(&matches)?;
     let mut turntable = match_turntable(&matches)?;
     let mut stdout = stdout();
     let no_color = match_no_color_mode(&matches);
+    let mut color_depth = match_color_depth(&matches);
+    let mut dither = matches.is_present("dither");
+    let mut background = match_background(&matches)?;
+    let stats = matches.is_present("stats");
+    let (mut frames, mut bytes) = (0, 0); // Drawn to the terminal so far
+    let image = match_image_mode(&matches);
     let mut webify = false;
     let mut webify_frame_count = 0;
     let mut webify_todo_frames = 0;
+    let mut webify_step = 0.0;
+    let (mut png, mut depth) = (None, None); // Pixel images to write instead of text
+    let (mut text_png, mut svg) = (None, None); // Pictures of the text to write instead of printing it
+    let (mut gif, mut cast) = (None, None); // Recordings of one turn to write instead of printing
+    let (mut recording_frames, mut recording_delay) = (0, 0.0);
+    let (mut recorded_frames, mut recording_step) = (0, 0.0);
 
-    let mut context: Context = Context::blank(match_image_mode(&matches)); // The context holds the frame+z buffer, and the width and height
-    if context.image {
+    let mut terminal_guard = None;
+    let mut renderer = Renderer::new(0, 0); // The renderer holds the frame+z buffer, and the width and height
+    renderer.context.image = image;
+    let (culling, winding) = match_culling(&matches);
+    renderer.cull(culling, winding);
+    let (mut shading, mut smooth_angle) = match_shading(&matches)?;
+    let mut ramp = match_ramp(&matches)?;
+    let mut pixel_mode = match_pixel_mode(&matches);
+    if image {
         if let Some(matches) = matches.subcommand_matches("image") {
-            match_dimensions(&mut context, &matches)?;
+            let (width, height) = match_dimensions(matches)?;
+            renderer.resize(width, height);
             turntable = match_turntable(matches)?;
-            if let Some(animation_frames) = matches.value_of("frame count") {
-                webify_todo_frames = animation_frames.parse()?;
+            scene.camera = match_camera(matches, &scene.meshes, cameras.first())?;
+            scene.lights = match_lights(matches)?;
+            let (culling, winding) = match_culling(matches);
+            renderer.cull(culling, winding);
+            let options = match_shading(matches)?;
+            shading = options.0;
+            smooth_angle = options.1;
+            ramp = match_ramp(matches)?.or(ramp);
+            pixel_mode = match_pixel_mode(matches).or(pixel_mode);
+            color_depth = match_color_depth(matches).or(color_depth);
+            dither |= matches.is_present("dither");
+            background = match_background(matches)?.or(background);
+            png = matches.value_of("png").map(PathBuf::from);
+            depth = matches.value_of("depth").map(PathBuf::from);
+            text_png = matches.value_of("text png").map(PathBuf::from);
+            svg = matches.value_of("svg").map(PathBuf::from);
+            let recording = match_recording(matches)?;
+            recording_frames = recording.0;
+            recording_delay = recording.1;
+            gif = match_gif(matches, recording_delay, create)?;
+            cast = match_cast(matches, (width, height), create)?;
+            if let Some(animation_frames) = match_webify(matches)? {
+                webify_todo_frames = animation_frames;
                 webify = true;
             }
         }
     } else {
-        crossterm::terminal::enable_raw_mode()?;
-        stdout.execute(cursor::Hide)?;
+        terminal_guard = Some(TerminalGuard::start(&mut stdout)?);
+    }
+
+    renderer.context.shading = shading;
+    if let Some(mode) = pixel_mode {
+        renderer.context.mode = mode;
+    }
+    if png.is_some() || depth.is_some() || gif.as_ref().is_some_and(|gif| gif.pixels) {
+        renderer.context.mode = PixelMode::Bitmap;
+    }
+    if cast.is_some() {
+        renderer.context.image = false; // Recorded the way it'd be drawn on a terminal
+        color_depth = color_depth.or(Some(ColorDepth::TrueColor)); // Whatever this terminal is
+    }
+    renderer.context.color_depth = color_depth.unwrap_or_else(ColorDepth::detect);
+    renderer.context.dither = dither;
+    if let Some(background) = background {
+        renderer.context.background = background;
+    }
+    if let Some(ramp) = ramp {
+        renderer.shader = Box::new(ramp);
+    }
+    for mesh in &mut scene.meshes {
+        mesh.smooth_normals(smooth_angle); // Models without normals get smooth ones
     }
-    let size: (u16, u16) = (0, 0); // This is the terminal size, it's used to check when a new context must be made
 
     if webify {
         println!("let frames = [");
-        turntable.3 = (2.0 * f32::consts::PI) * (1.0 / webify_todo_frames as f32);
+        // The frames make up one loop of the animation
+        webify_step = turntable.period().unwrap_or(0.0) / webify_todo_frames as f32;
     }
+    if gif.is_some() || cast.is_some() {
+        // Recordings loop seamlessly too
+        recording_step = turntable.period().unwrap_or(0.0) / recording_frames as f32;
+    }
+    let mut controls = Controls::new(&scene.camera);
     let mut last_time; // Used in the variable time step
     loop {
         last_time = Instant::now();
-        if !context.image {
-            if poll(target_frame_time - last_time.elapsed())? {
-                if let Event::Key(KeyEvent { code, modifiers }) = read()? {
-                    if code == KeyCode::Char('q')
-                        || (code == KeyCode::Char('c') && (modifiers == KeyModifiers::CONTROL))
-                    {
-                        stdout.execute(cursor::Show)?;
-                        crossterm::terminal::disable_raw_mode()?;
+        if !image && poll(target_frame_time - last_time.elapsed())? {
+            match read()? {
+                Event::Key(key) => {
+                    if controls.key(key, &mut scene, &mut renderer, &mut turntable) {
+                        drop(terminal_guard.take());
+                        if stats && frames > 0 {
+                            println!(
+                                "{} frames, {} bytes per frame on average",
+                                frames,
+                                bytes / frames
+                            );
+                        }
                         break;
                     }
                 }
+                Event::Mouse(mouse) => controls.mouse(mouse, &mut scene, &renderer.context),
+                Event::Resize(..) => {}
             }
         }
 
-        let rot =
-            Rotation3::from_euler_angles(turntable.0, turntable.1, turntable.2).to_homogeneous();
-        context.update(size, &mesh_queue)?; // This checks for if there needs to be a context update
-        context.clear(); // This clears the z and frame buffer
-        for mesh in &mesh_queue {
-            // Render all in mesh queue
-            draw_mesh(&mut context, &mesh, rot, default_shader); // Draw all meshes
+        if !image {
+            let (width, height) = terminal::size()?; // Follow the terminal's size
+            renderer.resize(width as usize, height as usize);
+        }
+        scene.transform = turntable.transform();
+        renderer.render(&scene); // This clears the z and frame buffer, then draws all meshes
+        controls.overlay(&mut renderer.context, turntable.velocity.norm());
+
+        if png.is_some() || depth.is_some() {
+            if let Some(path) = &png {
+                write_png(&renderer.context, create(path)?)?;
+            }
+            if let Some(path) = &depth {
+                write_depth_png(&renderer.context, create(path)?)?;
+            }
+            break;
+        }
+        if gif.is_some() || cast.is_some() {
+            if let Some(gif) = &mut gif {
+                gif.write_frame(&renderer.context)?;
+            }
+            if let Some(cast) = &mut cast {
+                let time = recorded_frames as f32 * recording_delay;
+                cast.write_frame(&mut renderer.context, !no_color, time)?;
+            }
+            recorded_frames += 1;
+            if recorded_frames == recording_frames {
+                if let Some(gif) = gif.take() {
+                    gif.finish()?;
+                }
+                if let Some(cast) = cast.take() {
+                    cast.finish()?;
+                }
+                break;
+            }
+            turntable.advance(recording_step);
+            continue;
+        }
+        if text_png.is_some() || svg.is_some() {
+            if let Some(path) = &text_png {
+                write_text_png(&renderer.context, create(path)?)?;
+            }
+            if let Some(path) = &svg {
+                write_svg(&renderer.context, create(path)?)?;
+            }
+            break;
         }
 
         if webify {
             println!("`");
         }
 
-        context.flush(!no_color, webify)?; // This prints all framebuffer info
+        renderer.context.flush(&mut stdout, !no_color, webify)?; // This prints all framebuffer info
         stdout.flush()?;
+        frames += 1;
+        bytes += renderer.context.bytes_flushed;
         let dt = Instant::now().duration_since(last_time).as_nanos() as f32 / 1_000_000_000.0;
-        turntable.1 += if webify {
-            turntable.3
-        } else {
-            (turntable.3 * dt) as f32
-        };
+        if webify {
+            turntable.advance(webify_step);
+        } else if !controls.paused {
+            turntable.advance(dt);
+        }
 
         if webify {
-            if turntable.1 > 9.42477 || webify_todo_frames - 1 == webify_frame_count {
+            if webify_frame_count + 1 == webify_todo_frames {
                 println!("`];");
                 break;
             } else {
@@ -105,7 +252,7 @@ fn main() -> Result<(), Box<dyn Error>> {
             webify_frame_count += 1;
         }
 
-        if context.image && !webify {
+        if image && !webify {
             break;
         }
     }
@@ -0,0 +1,173 @@
+use crate::geometry::{SimpleMesh, ToSimpleMesh, Triangle};
+use nalgebra::Vector3;
+use std::error::Error;
+use std::io::Read;
+
+/// A polygon mesh read from an ASCII OFF (Object File Format) file, with its vertex and face colors
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Off {
+    pub vertices: Vec<Vector3<f32>>,
+    pub colors: Option<Vec<(u8, u8, u8)>>, // Per vertex, in COFF files
+    pub faces: Vec<Vec<usize>>,            // Polygons, as indices into the vertices
+    pub face_colors: Vec<Option<(u8, u8, u8)>>,
+}
+
+/// An RGB color from the first three of `values`, which go from 0 to 1 if any of them is
+/// fractional, from 0 to 255 otherwise. A trailing alpha is ignored.
+fn color(values: &[&str]) -> Result<(u8, u8, u8), Box<dyn Error>> {
+    let scale = if values.iter().any(|value| value.contains('.')) {
+        255.0
+    } else {
+        1.0
+    };
+    let mut channels = [0; 3];
+    for (channel, value) in channels.iter_mut().zip(values) {
+        *channel = (value.parse::<f32>()? * scale).round().clamp(0.0, 255.0) as u8;
+    }
+    Ok((channels[0], channels[1], channels[2]))
+}
+
+impl Off {
+    pub fn read<R: Read>(mut reader: R) -> Result<Off, Box<dyn Error>> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        // Lines without comments or blanks, split into words
+        let mut lines = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or(""))
+            .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+            .filter(|words| !words.is_empty());
+
+        let mut words = lines.next().ok_or("off: empty file")?;
+        let keyword = words[0];
+        if !keyword.ends_with("OFF") {
+            return Err(format!("off: starts with {} instead of OFF", keyword).into());
+        }
+        if words.get(1) == Some(&"BINARY") {
+            return Err("off: binary files aren't supported".into());
+        }
+        let vertex_colors = keyword.contains('C');
+        let normals = keyword.contains('N');
+        words.remove(0);
+        if words.is_empty() {
+            words = lines.next().ok_or("off: no counts")?; // The counts have a line of their own
+        }
+        let vertex_count: usize = words.first().ok_or("off: no vertex count")?.parse()?;
+        let face_count: usize = words.get(1).ok_or("off: no face count")?.parse()?;
+
+        let mut off = Off::default();
+        if vertex_colors {
+            off.colors = Some(vec![]);
+        }
+        for _ in 0..vertex_count {
+            let words = lines.next().ok_or("off: file ends early")?;
+            if words.len() < 3 {
+                return Err("off: vertex with fewer than 3 coordinates".into());
+            }
+            off.vertices.push(Vector3::new(
+                words[0].parse()?,
+                words[1].parse()?,
+                words[2].parse()?,
+            ));
+            if let Some(colors) = &mut off.colors {
+                let skip = if normals { 6 } else { 3 }; // Texture coordinates come after the color
+                colors.push(match words.get(skip..skip + 3) {
+                    None => (0xFF, 0xFF, 0xFF),
+                    Some(values) => color(values)?,
+                });
+            }
+        }
+        for _ in 0..face_count {
+            let words = lines.next().ok_or("off: file ends early")?;
+            let count: usize = words[0].parse()?;
+            if words.len() < count + 1 {
+                return Err("off: face with fewer indices than it says".into());
+            }
+            let mut face = Vec::with_capacity(count);
+            for word in &words[1..=count] {
+                let index: usize = word.parse()?;
+                if index >= vertex_count {
+                    return Err(
+                        format!("off: face uses vertex {} of {}", index, vertex_count).into(),
+                    );
+                }
+                face.push(index);
+            }
+            off.faces.push(face);
+            // Without three values, a face has no color or an index into a color map
+            off.face_colors.push(match words.get(count + 1..count + 4) {
+                None => None,
+                Some(values) => Some(color(values)?),
+            });
+        }
+        Ok(off)
+    }
+}
+
+/// Splits every polygon into a fan of triangles, colored by their face, or else their vertices
+impl ToSimpleMesh for Off {
+    fn to_simple_mesh(&self) -> SimpleMesh {
+        let mut triangles = vec![];
+        for (face, face_color) in self.faces.iter().zip(&self.face_colors) {
+            for i in 1..face.len().saturating_sub(1) {
+                let corners = [face[0], face[i], face[i + 1]];
+                let position = |corner: usize| self.vertices[corners[corner]].insert_row(3, 1.0);
+                let mut triangle = Triangle {
+                    color: face_color.unwrap_or((0xFF, 0xFF, 0xFF)),
+                    v1: position(0),
+                    v2: position(1),
+                    v3: position(2),
+                    ..Triangle::default()
+                };
+                if let (None, Some(colors)) = (face_color, &self.colors) {
+                    triangle.colors = Some(corners.map(|corner| colors[corner]));
+                }
+                triangles.push(triangle);
+            }
+        }
+        SimpleMesh::new(triangles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector4;
+
+    #[test]
+    fn test_off() {
+        let text = "OFF
+# A square and a triangle
+5 2 0
+0 0 0
+1 0 0
+1 1 0
+0 1 0
+2 2 0
+4 0 1 2 3 255 0 0
+3 1 4 2
+";
+        let off = Off::read(text.as_bytes()).unwrap();
+        assert_eq!(off.vertices.len(), 5);
+        assert_eq!(off.faces, vec![vec![0, 1, 2, 3], vec![1, 4, 2]]);
+        assert_eq!(off.face_colors, vec![Some((255, 0, 0)), None]);
+        let mesh = off.to_simple_mesh();
+        assert_eq!(mesh.triangles.len(), 3);
+        assert_eq!(mesh.triangles[1].color, (255, 0, 0));
+        assert_eq!(mesh.triangles[2].color, (255, 255, 255));
+        assert_eq!(mesh.bounding_box.max, Vector4::new(2.0, 2.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_coff() {
+        let text = "COFF 3 1 0\n0 0 0 1.0 0.0 0.0 1.0\n1 0 0 0 1 0 1\n0 1 0 0 0 1 1\n3 0 1 2\n";
+        let off = Off::read(text.as_bytes()).unwrap();
+        assert_eq!(
+            off.colors,
+            Some(vec![(255, 0, 0), (0, 1, 0), (0, 0, 1)]) // Whole numbers go up to 255
+        );
+        let mesh = off.to_simple_mesh();
+        assert_eq!(mesh.triangles[0].colors.unwrap()[0], (255, 0, 0));
+        assert!(Off::read("OFF\n3 1 0\n0 0 0\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,389 @@
+use crate::geometry::{SimpleMesh, ToSimpleMesh, Triangle};
+use nalgebra::Vector3;
+use std::error::Error;
+use std::io::Read;
+use std::str::SplitAsciiWhitespace;
+
+/// The types a PLY property can have
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Scalar, Box<dyn Error>> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(format!("ply: unknown property type {}", name).into()),
+        })
+    }
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+    /// A color channel of this type, from 0 to 255. Floats go from 0 to 1.
+    fn channel(self, value: f64) -> u8 {
+        let value = match self {
+            Scalar::F32 | Scalar::F64 => value * 255.0,
+            Scalar::I16 | Scalar::U16 => value / 257.0,
+            _ => value,
+        };
+        value.round().clamp(0.0, 255.0) as u8
+    }
+}
+
+#[derive(Debug)]
+enum Property {
+    Scalar(Scalar, String),
+    List(Scalar, Scalar, String), // The type of the length, then of the items
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// A list length or vertex index, which has to be a whole number that isn't negative
+fn count(value: f64) -> Result<usize, Box<dyn Error>> {
+    if value < 0.0 || value.fract() != 0.0 {
+        return Err(format!("ply: {} isn't a count or an index", value).into());
+    }
+    Ok(value as usize)
+}
+
+/// The values in a PLY file's body, one after the other
+enum Body<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary(&'a [u8], bool), // The bytes left, and whether they're big endian
+}
+
+impl<'a> Body<'a> {
+    fn next(&mut self, scalar: Scalar) -> Result<f64, Box<dyn Error>> {
+        match self {
+            Body::Ascii(tokens) => match tokens.next() {
+                Some(token) => Ok(token.parse()?),
+                None => Err("ply: file ends early".into()),
+            },
+            Body::Binary(data, big_endian) => {
+                if data.len() < scalar.size() {
+                    return Err("ply: file ends early".into());
+                }
+                let (bytes, rest) = data.split_at(scalar.size());
+                *data = rest;
+                let mut buffer = [0; 8];
+                buffer[..bytes.len()].copy_from_slice(bytes);
+                if *big_endian {
+                    buffer[..bytes.len()].reverse();
+                }
+                let [b0, b1, b2, b3, ..] = buffer;
+                Ok(match scalar {
+                    Scalar::I8 => b0 as i8 as f64,
+                    Scalar::U8 => b0 as f64,
+                    Scalar::I16 => i16::from_le_bytes([b0, b1]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([b0, b1]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(buffer),
+                })
+            }
+        }
+    }
+}
+
+/// A polygon mesh read from a PLY file, in ASCII or binary, with the vertex normals and colors
+/// it comes with
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Ply {
+    pub vertices: Vec<Vector3<f32>>,
+    pub normals: Option<Vec<Vector3<f32>>>,
+    pub colors: Option<Vec<(u8, u8, u8)>>,
+    pub faces: Vec<Vec<usize>>, // Polygons, as indices into the vertices
+}
+
+impl Ply {
+    pub fn read<R: Read>(mut reader: R) -> Result<Ply, Box<dyn Error>> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        let end = b"\nend_header";
+        let header_end = data
+            .windows(end.len())
+            .position(|window| window == end)
+            .ok_or("ply: no end_header")?
+            + 1;
+        let mut body_start = header_end + end.len() - 1;
+        while body_start < data.len() && data[body_start - 1] != b'\n' {
+            body_start += 1; // Up to and including the line break
+        }
+        let header = std::str::from_utf8(&data[..header_end])?;
+
+        let mut lines = header.lines().map(str::trim);
+        if lines.next() != Some("ply") {
+            return Err("ply: doesn't start with ply".into());
+        }
+        let mut format = None;
+        let mut elements: Vec<Element> = vec![];
+        for line in lines {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["format", format_name, _version] => format = Some(format_name.to_string()),
+                ["element", name, count] => elements.push(Element {
+                    name: name.to_string(),
+                    count: count.parse()?,
+                    properties: vec![],
+                }),
+                ["property", "list", length, item, name] => elements
+                    .last_mut()
+                    .ok_or("ply: property before any element")?
+                    .properties
+                    .push(Property::List(
+                        Scalar::parse(length)?,
+                        Scalar::parse(item)?,
+                        name.to_string(),
+                    )),
+                ["property", scalar, name] => elements
+                    .last_mut()
+                    .ok_or("ply: property before any element")?
+                    .properties
+                    .push(Property::Scalar(Scalar::parse(scalar)?, name.to_string())),
+                ["comment", ..] | ["obj_info", ..] | [] => {}
+                _ => return Err(format!("ply: can't read header line {}", line).into()),
+            }
+        }
+        let mut body = match format.as_deref() {
+            Some("ascii") => {
+                Body::Ascii(std::str::from_utf8(&data[body_start..])?.split_ascii_whitespace())
+            }
+            Some("binary_little_endian") => Body::Binary(&data[body_start..], false),
+            Some("binary_big_endian") => Body::Binary(&data[body_start..], true),
+            Some(format) => return Err(format!("ply: unknown format {}", format).into()),
+            None => return Err("ply: no format".into()),
+        };
+
+        let mut ply = Ply::default();
+        for element in &elements {
+            let has = |names: &[&str]| {
+                element.properties.iter().any(|property| match property {
+                    Property::Scalar(_, name) => names.contains(&name.as_str()),
+                    _ => false,
+                })
+            };
+            let vertices = element.name == "vertex";
+            if vertices && has(&["nx"]) {
+                ply.normals = Some(vec![]);
+            }
+            if vertices && has(&["red", "r", "diffuse_red"]) {
+                ply.colors = Some(vec![]);
+            }
+            for _ in 0..element.count {
+                let (mut position, mut normal) = (Vector3::zeros(), Vector3::zeros());
+                let mut color = (0, 0, 0);
+                for property in &element.properties {
+                    match property {
+                        Property::Scalar(scalar, name) => {
+                            let value = body.next(*scalar)?;
+                            match name.as_str() {
+                                "x" => position.x = value as f32,
+                                "y" => position.y = value as f32,
+                                "z" => position.z = value as f32,
+                                "nx" => normal.x = value as f32,
+                                "ny" => normal.y = value as f32,
+                                "nz" => normal.z = value as f32,
+                                "red" | "r" | "diffuse_red" => color.0 = scalar.channel(value),
+                                "green" | "g" | "diffuse_green" => color.1 = scalar.channel(value),
+                                "blue" | "b" | "diffuse_blue" => color.2 = scalar.channel(value),
+                                _ => {}
+                            }
+                        }
+                        Property::List(length, item, name) => {
+                            let length = count(body.next(*length)?)?;
+                            let indices = name == "vertex_indices" || name == "vertex_index";
+                            let face = element.name == "face" && indices;
+                            let mut items = vec![];
+                            for _ in 0..length {
+                                let value = body.next(*item)?;
+                                if face {
+                                    items.push(count(value)?);
+                                }
+                            }
+                            if face {
+                                ply.faces.push(items);
+                            }
+                        }
+                    }
+                }
+                if vertices {
+                    ply.vertices.push(position);
+                    if let Some(normals) = &mut ply.normals {
+                        normals.push(normal);
+                    }
+                    if let Some(colors) = &mut ply.colors {
+                        colors.push(color);
+                    }
+                }
+            }
+        }
+        let count = ply.vertices.len();
+        if let Some(index) = ply.faces.iter().flatten().find(|index| **index >= count) {
+            return Err(format!("ply: face uses vertex {} of {}", index, count).into());
+        }
+        Ok(ply)
+    }
+}
+
+/// Splits every polygon into a fan of triangles, with the vertices' colors
+impl ToSimpleMesh for Ply {
+    fn to_simple_mesh(&self) -> SimpleMesh {
+        let mut triangles = vec![];
+        for face in &self.faces {
+            for i in 1..face.len().saturating_sub(1) {
+                let corners = [face[0], face[i], face[i + 1]];
+                let position = |corner: usize| self.vertices[corners[corner]].insert_row(3, 1.0);
+                let mut triangle = Triangle {
+                    color: (0xFF, 0xFF, 0xFF),
+                    v1: position(0),
+                    v2: position(1),
+                    v3: position(2),
+                    ..Triangle::default()
+                };
+                if let Some(normals) = &self.normals {
+                    let normal = |corner: usize| normals[corners[corner]].insert_row(3, 0.0);
+                    triangle.n1 = normal(0);
+                    triangle.n2 = normal(1);
+                    triangle.n3 = normal(2);
+                }
+                if let Some(colors) = &self.colors {
+                    triangle.colors = Some(corners.map(|corner| colors[corner]));
+                }
+                triangles.push(triangle);
+            }
+        }
+        SimpleMesh::new(triangles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector4;
+
+    const HEADER: &str = "ply
+format {} 1.0
+comment a colored quad
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+";
+
+    fn quad() -> Ply {
+        Ply {
+            vertices: vec![
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(1.0, 0.0, 0.0),
+                Vector3::new(1.0, 1.0, 0.0),
+                Vector3::new(0.0, 1.0, 0.0),
+            ],
+            normals: None,
+            colors: Some(vec![(255, 0, 0), (0, 255, 0), (0, 0, 255), (255, 255, 255)]),
+            faces: vec![vec![0, 1, 2, 3]],
+        }
+    }
+
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian {
+            "binary_big_endian"
+        } else {
+            "binary_little_endian"
+        };
+        let mut data = HEADER.replace("{}", format).into_bytes();
+        let ply = quad();
+        let colors = ply.colors.as_ref().unwrap();
+        for (vertex, color) in ply.vertices.iter().zip(colors) {
+            for value in vertex.iter() {
+                if big_endian {
+                    data.extend_from_slice(&value.to_be_bytes());
+                } else {
+                    data.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            data.extend_from_slice(&[color.0, color.1, color.2]);
+        }
+        data.push(4);
+        for index in [0i32, 1, 2, 3, 0, 1].iter() {
+            // The face, then the edge
+            if big_endian {
+                data.extend_from_slice(&index.to_be_bytes());
+            } else {
+                data.extend_from_slice(&index.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_ascii() {
+        let body =
+            "0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 0 0 0 255\n0 1 0 255 255 255\n4 0 1 2 3\n0 1\n";
+        let data = HEADER.replace("{}", "ascii") + body;
+        assert_eq!(Ply::read(data.as_bytes()).unwrap(), quad());
+        for face in &["4 0 1 2 -1", "4 0 1 2 2.5"] {
+            let bad = data.replace("4 0 1 2 3", face);
+            assert!(Ply::read(bad.as_bytes()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_binary() {
+        assert_eq!(Ply::read(&binary(false)[..]).unwrap(), quad());
+        assert_eq!(Ply::read(&binary(true)[..]).unwrap(), quad());
+        let mut short = binary(false);
+        short.truncate(short.len() - 3);
+        assert!(Ply::read(&short[..]).is_err());
+        // Counts come from the file, so they mustn't be trusted with an allocation
+        let huge = HEADER
+            .replace("{}", "binary_little_endian")
+            .replace("vertex 4", "vertex 99999999999999");
+        assert!(Ply::read(huge.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_triangulation() {
+        let mesh = quad().to_simple_mesh();
+        assert_eq!(mesh.triangles.len(), 2);
+        assert_eq!(
+            mesh.triangles[1].colors,
+            Some([(255, 0, 0), (0, 0, 255), (255, 255, 255)])
+        );
+        assert_eq!(mesh.bounding_box.max, Vector4::new(1.0, 1.0, 0.0, 1.0));
+    }
+}
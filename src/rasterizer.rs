@@ -1,28 +1,43 @@
-use crate::context::Context;
+use crate::clipping::clip_triangle;
+use crate::context::{Cell, Context};
 use crate::geometry::{SimpleMesh, Triangle};
-use nalgebra::{Matrix4, Vector4};
+use crate::lighting::{illuminate, Illumination, Light, Specular};
+use crate::shader::{Fragment, FragmentShader};
+use crate::texture::{modulate, Texture};
+use nalgebra::{Matrix3, Matrix4, Vector3, Vector4};
 
-pub fn default_shader(shade: f32) -> char {
-    if shade <= 0.20 {
-        '.'
-    } else if shade <= 0.30 {
-        ':'
-    } else if shade <= 0.40 {
-        '-'
-    } else if shade <= 0.50 {
-        '='
-    } else if shade <= 0.60 {
-        '+'
-    } else if shade <= 0.70 {
-        '*'
-    } else if shade <= 0.80 {
-        '#'
-    } else if shade <= 0.90 {
-        '%'
-    } else if shade <= 1.0 {
-        '@'
-    } else {
-        ' '
+/// Which side of the triangles gets skipped when rasterizing
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Culling {
+    None,
+    Back,
+    Front,
+}
+
+/// The order a triangle's vertices go around in, as seen from its front
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Winding {
+    CounterClockwise,
+    Clockwise,
+}
+
+/// How the shade varies across a triangle
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Shading {
+    Flat,    // One shade per triangle, from its face normal
+    Gouraud, // Shades the vertices, then blends the shades across the triangle
+    Phong,   // Blends the vertex normals across the triangle, then shades every pixel
+}
+
+impl Culling {
+    /// Whether a triangle winding `counter_clockwise` on screen should be skipped
+    pub fn culls(self, counter_clockwise: bool, front: Winding) -> bool {
+        let front_facing = counter_clockwise == (front == Winding::CounterClockwise);
+        match self {
+            Culling::None => false,
+            Culling::Back => !front_facing,
+            Culling::Front => front_facing,
+        }
     }
 }
 
@@ -36,58 +51,198 @@ fn orient_triangle(triangle: &Triangle) -> f32 {
 }
 
 // Writes multiple meshes to context
-pub fn draw_mesh<F>(context: &mut Context, mesh: &SimpleMesh, transform: Matrix4<f32>, shader: F)
+pub fn draw_mesh<S>(context: &mut Context, mesh: &SimpleMesh, transform: Matrix4<f32>, shader: &S)
 where
-    F: Fn(f32) -> char,
+    S: FragmentShader + ?Sized,
 {
+    let lights = context.lights.clone(); // The context is drawn to while the lights are in use
+    let texture = mesh.texture.as_deref();
     for triangle in &mesh.triangles {
-        draw_triangle(context, &triangle, transform, &shader);
+        draw_triangle(
+            context,
+            triangle,
+            transform,
+            &lights,
+            &mesh.specular,
+            texture,
+            shader,
+        );
     }
 }
 
-pub fn draw_triangle<F>(
+// Perspective divide, then maps normalized device coordinates onto a `width` by `height` pixel grid.
+// The w component keeps 1/w, to interpolate attributes with perspective correction
+fn viewport(clip: Vector4<f32>, width: f32, height: f32) -> Vector4<f32> {
+    let ndc = clip / clip.w;
+    Vector4::new(
+        (ndc.x + 1.0) * 0.5 * width,
+        (1.0 - ndc.y) * 0.5 * height, // Rows grow downwards
+        ndc.z,
+        1.0 / clip.w,
+    )
+}
+
+pub fn draw_triangle<S>(
     context: &mut Context,
     triangle: &Triangle,
     transform: Matrix4<f32>,
-    shader: F,
+    lights: &[Light],
+    specular: &Specular,
+    texture: Option<&Texture>,
+    shader: &S,
+) where
+    S: FragmentShader + ?Sized,
+{
+    let mut view_triangle = triangle.clone();
+    view_triangle.mul(context.view * transform);
+    let face = view_triangle.normal().into_inner().xyz();
+    let smooth = context.shading != Shading::Flat && view_triangle.has_normals();
+    let normals = [view_triangle.n1, view_triangle.n2, view_triangle.n3].map(|normal| {
+        if smooth && normal.norm() > 0.0 {
+            normal.xyz().normalize()
+        } else {
+            face
+        }
+    });
+    let positions = [view_triangle.v1, view_triangle.v2, view_triangle.v3].map(|v| v.xyz());
+    let blend = |values: &[Vector3<f32>; 3], barycentric: &Vector3<f32>| {
+        values[0] * barycentric.x + values[1] * barycentric.y + values[2] * barycentric.z
+    };
+    let mode = context.mode;
+    let shading = if smooth {
+        context.shading
+    } else {
+        Shading::Flat
+    };
+    let flat = illuminate(
+        lights,
+        &blend(&positions, &Vector3::repeat(1.0 / 3.0)),
+        &face,
+        specular,
+    );
+    let lit_vertices = if shading == Shading::Gouraud {
+        [0, 1, 2].map(|i| illuminate(lights, &positions[i], &normals[i], specular))
+    } else {
+        [flat; 3]
+    };
+    let fragment = |x: usize, y: usize, depth: f32, barycentric: Vector3<f32>| {
+        let normal = blend(&normals, &barycentric).normalize();
+        let illumination = match shading {
+            Shading::Flat => flat,
+            Shading::Gouraud => Illumination {
+                diffuse: blend(&lit_vertices.map(|lit| lit.diffuse), &barycentric),
+                specular: blend(&lit_vertices.map(|lit| lit.specular), &barycentric),
+            },
+            Shading::Phong => {
+                illuminate(lights, &blend(&positions, &barycentric), &normal, specular)
+            }
+        };
+        let uv = triangle
+            .uv
+            .map(|uv| uv[0] * barycentric.x + uv[1] * barycentric.y + uv[2] * barycentric.z);
+        let color = match (texture, uv) {
+            (Some(texture), Some(uv)) => {
+                modulate(triangle.color_at(&barycentric), texture.sample(uv))
+            }
+            _ => triangle.color_at(&barycentric),
+        };
+        shader.shade(&Fragment {
+            x,
+            y,
+            depth,
+            normal,
+            barycentric,
+            color,
+            uv,
+            illumination,
+            mode,
+        })
+    };
+
+    let mut clip_space = triangle.clone();
+    clip_space.mul(context.utransform * transform);
+    if context.culling != Culling::None {
+        let (v1, v2, v3) = (clip_space.v1, clip_space.v2, clip_space.v3);
+        // The homogeneous determinant has the sign of the on-screen area, even for vertices behind the eye
+        let facing =
+            Matrix3::new(v1.x, v1.y, v1.w, v2.x, v2.y, v2.w, v3.x, v3.y, v3.w).determinant();
+        if context.culling.culls(facing > 0.0, context.winding) {
+            return;
+        }
+    }
+    let (width, height) = context.resolution();
+    let (width, height) = (width as f32, height as f32);
+    for part in clip_triangle([clip_space.v1, clip_space.v2, clip_space.v3]) {
+        if part.iter().any(|vertex| vertex.position.w <= f32::EPSILON) {
+            continue; // Degenerated into the eye
+        }
+        let dist_triangle = Triangle {
+            color: triangle.color,
+            v1: viewport(part[0].position, width, height),
+            v2: viewport(part[1].position, width, height),
+            v3: viewport(part[2].position, width, height),
+            ..Triangle::default()
+        };
+        let corners = [
+            part[0].barycentric,
+            part[1].barycentric,
+            part[2].barycentric,
+        ];
+        rasterize(context, &dist_triangle, corners, fragment);
+    }
+}
+
+// Fills the pixels covered by a screen space triangle. `corners` locate its vertices inside of the
+// original triangle, `fragment` is given a pixel's location inside of the original triangle too.
+// Pixels the fragment discards keep their depth
+fn rasterize<S>(
+    context: &mut Context,
+    dist_triangle: &Triangle,
+    corners: [Vector3<f32>; 3],
+    fragment: S,
 ) where
-    F: Fn(f32) -> char,
+    S: Fn(usize, usize, f32, Vector3<f32>) -> Option<Cell>,
 {
-    let mut dist_triangle = triangle.clone();
-    dist_triangle.mul(context.utransform * transform);
     let aabb = dist_triangle.aabb(); // Calculate triangle bounds
     let mins: (usize, usize) = (
-        aabb.min[0].max(1.0).ceil() as usize,
-        aabb.min[1].max(1.0).ceil() as usize,
+        aabb.min[0].max(0.0).floor() as usize,
+        aabb.min[1].max(0.0).floor() as usize,
     );
+    let (width, height) = context.resolution();
     let maxs: (usize, usize) = (
-        (aabb.max[0] * 2.0).min((context.width - 1) as f32).ceil() as usize,
-        aabb.max[1].min((context.height - 1) as f32).ceil() as usize,
+        aabb.max[0].ceil().min(width as f32).max(0.0) as usize,
+        aabb.max[1].ceil().min(height as f32).max(0.0) as usize,
     );
-    let a = 1.0 / orient_triangle(&dist_triangle);
+    let area = orient_triangle(dist_triangle);
+    if area == 0.0 {
+        return; // Seen edge-on
+    }
+    let a = 1.0 / area; // Normalizes the edge functions into barycentric coordinates, whichever way the triangle winds
 
     for y in mins.1..maxs.1 {
         for x in mins.0..maxs.0 {
-            let p = Vector4::new(x as f32, y as f32, 0.0, 0.0);
-            let w0 = orient(&dist_triangle.v2, &dist_triangle.v3, &p);
-            let w1 = orient(&dist_triangle.v3, &dist_triangle.v1, &p);
-            let w2 = orient(&dist_triangle.v1, &dist_triangle.v2, &p);
+            let p = Vector4::new(x as f32 + 0.5, y as f32 + 0.5, 0.0, 0.0); // Sample the pixel's center
+            let w0 = orient(&dist_triangle.v2, &dist_triangle.v3, &p) * a;
+            let w1 = orient(&dist_triangle.v3, &dist_triangle.v1, &p) * a;
+            let w2 = orient(&dist_triangle.v1, &dist_triangle.v2, &p) * a;
             if w0 >= 0.0 && w1 >= 0.0 && w2 >= 0.0 {
-                let pixel_shade = dist_triangle.normal().z * a * (w0 + w1 + w2);
-                let z = dist_triangle.v1[2]
-                    + a * (w1 * (dist_triangle.v2[2] - dist_triangle.v1[2])
-                        + w2 * (dist_triangle.v3[2] - dist_triangle.v1[2]));
-                let id = y * context.width + x * 2;
+                let z = w0 * dist_triangle.v1.z + w1 * dist_triangle.v2.z + w2 * dist_triangle.v3.z;
+                let id = y * width + x;
                 if z < context.z_buffer[id] {
-                    context.z_buffer[id] = z;
-                    let pixel = (shader(pixel_shade), dist_triangle.color);
-                    context.frame_buffer[id] = pixel;
-                    context.frame_buffer[id + 1] = pixel;
+                    // Weigh by 1/w so attributes don't swim across the triangle in perspective
+                    let (p0, p1, p2) = (
+                        w0 * dist_triangle.v1.w,
+                        w1 * dist_triangle.v2.w,
+                        w2 * dist_triangle.v3.w,
+                    );
+                    let barycentric =
+                        (corners[0] * p0 + corners[1] * p1 + corners[2] * p2) / (p0 + p1 + p2);
+                    if let Some(cell) = fragment(x, y, z, barycentric) {
+                        context.z_buffer[id] = z;
+                        context.pixels[id] = Some(cell);
+                    }
                 }
             }
         }
-        if context.image {
-            context.frame_buffer[y * context.width + 1] = ('\n', (0, 0, 0));
-        }
     }
 }
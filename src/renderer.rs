@@ -0,0 +1,220 @@
+use crate::camera::Camera;
+use crate::context::Context;
+use crate::geometry::SimpleMesh;
+use crate::lighting::Light;
+use crate::rasterizer::{draw_mesh, Culling, Winding};
+use crate::shader::{default_shader, FragmentShader};
+use nalgebra::{Matrix4, Rotation3};
+
+/// Everything that is drawn in a frame: the meshes, the model transform applied to all of them,
+/// the camera they're seen through and the lights shining on them. Scenes without lights are lit
+/// by a headlight shining down the view axis.
+pub struct Scene {
+    pub meshes: Vec<SimpleMesh>,
+    pub transform: Matrix4<f32>,
+    pub camera: Camera,
+    pub lights: Vec<Light>,
+}
+
+impl Scene {
+    /// Creates a scene with a camera that fits all of `meshes` in view
+    pub fn new(meshes: Vec<SimpleMesh>) -> Scene {
+        Scene {
+            camera: Camera::fit(&meshes),
+            meshes,
+            transform: Matrix4::identity(),
+            lights: vec![],
+        }
+    }
+    /// Sets the scene's transform from euler angles (in radians)
+    pub fn rotate(&mut self, roll: f32, pitch: f32, yaw: f32) -> &mut Scene {
+        self.transform = Rotation3::from_euler_angles(roll, pitch, yaw).to_homogeneous();
+        self
+    }
+}
+
+/// Draws scenes into an in-memory frame buffer of `width` by `height` cells, turning every pixel
+/// into a cell with `shader`.
+pub struct Renderer {
+    pub context: Context,
+    pub shader: Box<dyn FragmentShader>,
+}
+
+impl Renderer {
+    pub fn new(width: usize, height: usize) -> Renderer {
+        let mut context = Context::blank(true);
+        context.width = width;
+        context.height = height;
+        Renderer {
+            context,
+            shader: Box::new(default_shader),
+        }
+    }
+    pub fn resize(&mut self, width: usize, height: usize) {
+        if (width, height) != (self.context.width, self.context.height) {
+            self.context.invalidate(); // The last frame's cells are somewhere else now
+        }
+        self.context.width = width;
+        self.context.height = height;
+    }
+    /// Skips the `culling` side of every triangle, front faces being the ones winding in `front` order
+    pub fn cull(&mut self, culling: Culling, front: Winding) -> &mut Renderer {
+        self.context.culling = culling;
+        self.context.winding = front;
+        self
+    }
+    /// Clears the frame buffer and rasterizes every mesh of the scene into it
+    pub fn render(&mut self, scene: &Scene) -> &Context {
+        let size = (self.context.width, self.context.height);
+        self.context.update(size, &scene.camera);
+        self.context.light(&scene.lights);
+        self.context.clear();
+        for mesh in &scene.meshes {
+            draw_mesh(&mut self.context, mesh, scene.transform, &*self.shader);
+        }
+        self.context.resolve();
+        &self.context
+    }
+    /// The last rendered frame as plain text, one line per row
+    pub fn text(&self) -> String {
+        let mut text = String::with_capacity(self.context.frame_buffer.len() + self.context.height);
+        for row in self.context.frame_buffer.chunks(self.context.width.max(1)) {
+            text.extend(row.iter().map(|cell| cell.glyph));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::{Cell, PixelMode};
+    use crate::geometry::{Triangle, AABB};
+    use crate::lighting::Specular;
+    use crate::shader::Fragment;
+    use nalgebra::Vector4;
+
+    fn square() -> Scene {
+        let corner = |x, y| Vector4::new(x, y, 0.0, 1.0);
+        Scene::new(vec![SimpleMesh {
+            bounding_box: AABB::new(corner(-1.0, -1.0), corner(1.0, 1.0)),
+            triangles: vec![
+                Triangle {
+                    color: (255, 0, 0),
+                    v1: corner(-1.0, -1.0),
+                    v2: corner(1.0, -1.0),
+                    v3: corner(1.0, 1.0),
+                    ..Triangle::default()
+                },
+                Triangle {
+                    color: (255, 0, 0),
+                    v1: corner(-1.0, -1.0),
+                    v2: corner(1.0, 1.0),
+                    v3: corner(-1.0, 1.0),
+                    ..Triangle::default()
+                },
+            ],
+            specular: Specular::default(),
+            texture: None,
+        }])
+    }
+
+    #[test]
+    fn test_send() {
+        // Scenes can be built on one thread and rendered on another
+        fn send<T: Send + Sync>(_: T) {}
+        send(square());
+    }
+
+    #[test]
+    fn test_render_in_memory() {
+        let mut renderer = Renderer::new(40, 20);
+        let context = renderer.render(&square());
+        assert_eq!(context.frame_buffer.len(), 40 * 20);
+        assert!(context.frame_buffer.iter().any(|cell| cell.glyph != ' '));
+        assert_eq!(renderer.text().lines().count(), 20);
+    }
+
+    #[test]
+    fn test_render_centered() {
+        let mut renderer = Renderer::new(40, 20);
+        renderer.render(&square());
+        let center = &renderer.context.frame_buffer[10 * 40 + 20];
+        assert_ne!(center.glyph, ' ');
+        assert_eq!(center.foreground, (255, 0, 0));
+        assert_eq!(renderer.context.frame_buffer[0].glyph, ' ');
+    }
+
+    #[test]
+    fn test_custom_shader() {
+        let mut renderer = Renderer::new(40, 20);
+        // Keeps the left half of the square only, checkered by pixel
+        renderer.shader = Box::new(|fragment: &Fragment| {
+            if fragment.x >= 10 {
+                return None;
+            }
+            Some(Cell {
+                glyph: if (fragment.x + fragment.y) % 2 == 0 {
+                    'x'
+                } else {
+                    'o'
+                },
+                foreground: (255, 255, 255),
+                background: Some((0, 0, 255)),
+            })
+        });
+        renderer.render(&square());
+        let row = &renderer.context.frame_buffer[10 * 40..11 * 40];
+        assert!(row[..20].iter().any(|cell| cell.glyph == 'x'));
+        assert!(row[20..].iter().all(|cell| *cell == Cell::blank()));
+        assert_eq!(row[18].background, Some((0, 0, 255)));
+    }
+
+    #[test]
+    fn test_half_blocks() {
+        let mut renderer = Renderer::new(40, 20);
+        renderer.context.mode = PixelMode::HalfBlock;
+        renderer.render(&square());
+        assert_eq!(renderer.context.resolution(), (40, 40));
+        let center = &renderer.context.frame_buffer[10 * 40 + 20];
+        assert_eq!(center.glyph, '▀');
+        assert_eq!(center.background, Some(center.foreground));
+        assert_eq!(center.foreground, (255, 0, 0));
+        assert_eq!(renderer.context.frame_buffer[0], Cell::blank());
+    }
+
+    #[test]
+    fn test_braille() {
+        let mut renderer = Renderer::new(40, 20);
+        renderer.context.mode = PixelMode::Braille;
+        renderer.render(&square());
+        assert_eq!(renderer.context.resolution(), (80, 80));
+        let center = &renderer.context.frame_buffer[10 * 40 + 20];
+        assert_eq!(center.glyph, '⣿');
+        assert_eq!(center.foreground, (255, 0, 0));
+        assert_eq!(renderer.context.frame_buffer[0], Cell::blank());
+        // The square's edges only cover some of their cells' dots
+        assert!(renderer
+            .context
+            .frame_buffer
+            .iter()
+            .any(|cell| ('\u{2801}'..'\u{28FF}').contains(&cell.glyph)));
+    }
+
+    #[test]
+    fn test_culling() {
+        let drawn = |culling, front| {
+            let mut renderer = Renderer::new(40, 20);
+            renderer.cull(culling, front);
+            let context = renderer.render(&square());
+            context.frame_buffer.iter().any(|cell| cell.glyph != ' ')
+        };
+        // The square winds counter-clockwise towards the camera
+        assert!(drawn(Culling::None, Winding::Clockwise));
+        assert!(drawn(Culling::Back, Winding::CounterClockwise));
+        assert!(!drawn(Culling::Front, Winding::CounterClockwise));
+        assert!(!drawn(Culling::Back, Winding::Clockwise));
+        assert!(drawn(Culling::Front, Winding::Clockwise));
+    }
+}
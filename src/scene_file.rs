@@ -0,0 +1,110 @@
+use nalgebra::{Point3, Vector3};
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use sloth::Light;
+
+use crate::inputs::parse_color;
+
+/// The settings a scene file can hold, written in TOML:
+///
+/// ```toml
+/// [[lights]]
+/// type = "directional"
+/// direction = [-1.0, -1.0, -1.0]
+/// color = "#ffeedd"
+/// intensity = 0.9
+///
+/// [[lights]]
+/// type = "ambient"
+/// intensity = 0.1
+///
+/// [turntable]
+/// speed = 0.5            # Radians per second
+/// axis = [0.0, 1.0, 0.0] # Or spin = [x, y, z] radians per second around each axis
+/// oscillate = 30.0       # Degrees either side, instead of spinning all the way around
+/// ```
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SceneFile {
+    #[serde(default)]
+    pub lights: Vec<LightEntry>,
+    pub turntable: Option<TurntableEntry>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TurntableEntry {
+    pub speed: Option<f32>,
+    pub axis: Option<[f32; 3]>,
+    pub spin: Option<[f32; 3]>,
+    pub oscillate: Option<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum LightEntry {
+    Ambient {
+        color: Option<String>,
+        intensity: Option<f32>,
+    },
+    Directional {
+        direction: [f32; 3],
+        color: Option<String>,
+        intensity: Option<f32>,
+    },
+    Point {
+        position: [f32; 3],
+        color: Option<String>,
+        intensity: Option<f32>,
+    },
+}
+
+impl SceneFile {
+    pub fn load(path: &Path) -> Result<SceneFile, Box<dyn Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("scene file: [{}] couldn't load, {}", path.display(), e))?;
+        toml::from_str(&text)
+            .map_err(|e| format!("scene file: [{}] couldn't parse, {}", path.display(), e).into())
+    }
+    pub fn lights(&self) -> Result<Vec<Light>, Box<dyn Error>> {
+        let color = |color: &Option<String>| match color {
+            Some(color) => parse_color(color),
+            None => Ok(Vector3::new(1.0, 1.0, 1.0)),
+        };
+        self.lights
+            .iter()
+            .map(|entry| {
+                Ok(match entry {
+                    LightEntry::Ambient {
+                        color: c,
+                        intensity,
+                    } => Light::Ambient {
+                        color: color(c)?,
+                        intensity: intensity.unwrap_or(0.1),
+                    },
+                    LightEntry::Directional {
+                        direction,
+                        color: c,
+                        intensity,
+                    } => Light::Directional {
+                        direction: Vector3::from(*direction),
+                        color: color(c)?,
+                        intensity: intensity.unwrap_or(1.0),
+                    },
+                    LightEntry::Point {
+                        position,
+                        color: c,
+                        intensity,
+                    } => Light::Point {
+                        position: Point3::from(Vector3::from(*position)),
+                        color: color(c)?,
+                        intensity: intensity.unwrap_or(1.0),
+                    },
+                })
+            })
+            .collect()
+    }
+}
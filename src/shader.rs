@@ -0,0 +1,219 @@
+use crate::context::{Cell, PixelMode};
+use crate::lighting::Illumination;
+use nalgebra::{Vector2, Vector3};
+
+/// Everything known about a pixel a triangle covers, handed to a `FragmentShader`
+pub struct Fragment {
+    pub x: usize, // The pixel's column, see `PixelMode` for how pixels map onto cells
+    pub y: usize,
+    pub depth: f32,                 // From -1 at the near plane to 1 at the far plane
+    pub normal: Vector3<f32>,       // Interpolated, in view space
+    pub barycentric: Vector3<f32>, // Where the pixel is inside of the triangle, from its three vertices
+    pub color: (u8, u8, u8),       // The triangle's material color
+    pub uv: Option<Vector2<f32>>,  // Interpolated texture coordinates, if the model has them
+    pub illumination: Illumination, // How the scene's lights reach the pixel
+    pub mode: PixelMode,
+}
+
+impl Fragment {
+    /// The cell the built-in shaders draw with `glyph`. Glyphs show how bright a pixel is, so its
+    /// color only needs the light's tint. Pixel modes drop the glyph, so there the color is lit.
+    fn cell(&self, glyph: char) -> Cell {
+        let foreground = match self.mode {
+            PixelMode::Glyph => self.illumination.tint(self.color),
+            _ => self.illumination.lit(self.color),
+        };
+        Cell {
+            glyph,
+            foreground,
+            background: None,
+        }
+    }
+}
+
+/// Turns fragments into cells. Returning `None` discards the fragment, leaving the pixel (and the
+/// z-buffer) as they were.
+pub trait FragmentShader {
+    fn shade(&self, fragment: &Fragment) -> Option<Cell>;
+}
+
+impl<F> FragmentShader for F
+where
+    F: Fn(&Fragment) -> Option<Cell>,
+{
+    fn shade(&self, fragment: &Fragment) -> Option<Cell> {
+        self(fragment)
+    }
+}
+
+/// Picks a glyph as dense as the light on the pixel, in the material's color tinted by the light
+pub fn default_shader(fragment: &Fragment) -> Option<Cell> {
+    let shade = fragment.illumination.shade();
+    let glyph = if shade <= 0.20 {
+        '.'
+    } else if shade <= 0.30 {
+        ':'
+    } else if shade <= 0.40 {
+        '-'
+    } else if shade <= 0.50 {
+        '='
+    } else if shade <= 0.60 {
+        '+'
+    } else if shade <= 0.70 {
+        '*'
+    } else if shade <= 0.80 {
+        '#'
+    } else if shade <= 0.90 {
+        '%'
+    } else if shade <= 1.0 {
+        '@'
+    } else {
+        ' '
+    };
+    Some(fragment.cell(glyph))
+}
+
+/// Named glyph ramps, from the dimmest glyph to the brightest
+pub const RAMP_PRESETS: [(&str, &str); 5] = [
+    ("standard", ".:-=+*#%@"),
+    (
+        "bourke", // Paul Bourke's 70 levels of gray
+        " .'`^\",:;Il!i><~+_-?][}{1)(|\\/tfjrxnuvczXYUJCLQ0OZmwqpdbkhao*#MW&8%B@$",
+    ),
+    ("blocks", "░▒▓█"),
+    ("digits", "0123456789"),
+    ("dense", "·∙•:;+=xX$#▒▓█"),
+];
+
+/// Picks glyphs from a ramp of evenly spaced shades, after bending the shade with a gamma curve
+/// and stretching it around the middle gray by `contrast`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Ramp {
+    pub glyphs: Vec<char>, // From the dimmest to the brightest
+    pub gamma: f32,
+    pub contrast: f32,
+}
+
+impl Ramp {
+    pub fn new(glyphs: &str) -> Ramp {
+        Ramp {
+            glyphs: glyphs.chars().collect(),
+            gamma: 1.0,
+            contrast: 1.0,
+        }
+    }
+    /// The preset called `name`, if there is one
+    pub fn preset(name: &str) -> Option<Ramp> {
+        RAMP_PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, glyphs)| Ramp::new(glyphs))
+    }
+    /// The glyph for a shade from 0 to 1
+    pub fn glyph(&self, shade: f32) -> char {
+        if self.glyphs.is_empty() {
+            return ' ';
+        }
+        let shade = shade.clamp(0.0, 1.0).powf(1.0 / self.gamma);
+        let shade = ((shade - 0.5) * self.contrast + 0.5).clamp(0.0, 1.0);
+        let last = self.glyphs.len() - 1;
+        self.glyphs[((shade * self.glyphs.len() as f32) as usize).min(last)]
+    }
+}
+
+impl Default for Ramp {
+    fn default() -> Self {
+        Ramp::new(RAMP_PRESETS[0].1)
+    }
+}
+
+impl FragmentShader for Ramp {
+    fn shade(&self, fragment: &Fragment) -> Option<Cell> {
+        Some(fragment.cell(self.glyph(fragment.illumination.shade())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragment(shade: f32) -> Fragment {
+        Fragment {
+            x: 0,
+            y: 0,
+            depth: 0.0,
+            normal: Vector3::z(),
+            barycentric: Vector3::x(),
+            color: (200, 100, 0),
+            uv: None,
+            illumination: Illumination {
+                diffuse: Vector3::repeat(shade),
+                specular: Vector3::zeros(),
+            },
+            mode: PixelMode::Glyph,
+        }
+    }
+
+    #[test]
+    fn test_default_shader() {
+        let bright = default_shader(&fragment(1.0)).unwrap();
+        assert_eq!(bright.glyph, '@');
+        assert_eq!(bright.foreground, (200, 100, 0));
+        assert_eq!(default_shader(&fragment(0.1)).unwrap().glyph, '.');
+        let dim = Fragment {
+            mode: PixelMode::HalfBlock,
+            ..fragment(0.5)
+        };
+        assert_eq!(default_shader(&dim).unwrap().foreground, (100, 50, 0));
+    }
+
+    #[test]
+    fn test_ramp() {
+        let ramp = Ramp::new("abcd");
+        assert_eq!(ramp.glyph(0.0), 'a');
+        assert_eq!(ramp.glyph(0.3), 'b');
+        assert_eq!(ramp.glyph(0.74), 'c');
+        assert_eq!(ramp.glyph(1.0), 'd');
+        assert_eq!(Ramp::new("").glyph(0.5), ' ');
+
+        let bright = Ramp {
+            gamma: 2.0,
+            ..ramp.clone()
+        };
+        assert_eq!(bright.glyph(0.3), 'c');
+        let flat = Ramp {
+            contrast: 0.0,
+            ..ramp
+        };
+        assert_eq!(flat.glyph(0.0), flat.glyph(1.0));
+    }
+
+    #[test]
+    fn test_ramp_presets() {
+        assert_eq!(Ramp::preset("bourke").unwrap().glyphs.len(), 70);
+        assert_eq!(Ramp::preset("blocks").unwrap().glyph(1.0), '█');
+        assert_eq!(Ramp::preset("digits").unwrap().glyph(0.55), '5');
+        assert!(Ramp::preset("nope").is_none());
+    }
+
+    #[test]
+    fn test_closures_are_shaders() {
+        // Shows the depth instead of the lighting, and discards everything past the middle
+        let depth = |fragment: &Fragment| {
+            if fragment.depth > 0.0 {
+                return None;
+            }
+            Some(Cell {
+                glyph: '#',
+                foreground: (255, 255, 255),
+                background: Some((0, 0, 0)),
+            })
+        };
+        assert!(depth.shade(&fragment(1.0)).is_some());
+        let far = Fragment {
+            depth: 0.5,
+            ..fragment(1.0)
+        };
+        assert!(depth.shade(&far).is_none());
+    }
+}
@@ -0,0 +1,197 @@
+use crate::geometry::{SimpleMesh, ToSimpleMesh, Triangle};
+use nalgebra::Vector3;
+use std::error::Error;
+use std::io::{Cursor, Read};
+
+/// The color of STL facets that don't have one, unless told otherwise
+pub const STL_COLOR: (u8, u8, u8) = (0xFF, 0xFF, 0x00);
+
+/// The facets of an STL file, with their colors. Binary files can keep a color in every facet's
+/// attribute bytes, either the VisCAM and SolidView way or the Materialise Magics way, whose
+/// header also holds a `COLOR=` for the whole object. ASCII files have no colors.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Stl {
+    pub facets: Vec<[Vector3<f32>; 3]>,
+    pub colors: Vec<Option<(u8, u8, u8)>>, // Per facet
+    pub color: Option<(u8, u8, u8)>,       // For the whole object, from the header
+}
+
+/// Scales a 5 bit channel up to 8 bits
+fn channel(attribute: u16, shift: u16) -> u8 {
+    let value = (attribute >> shift) & 0x1F;
+    ((value << 3) | (value >> 2)) as u8
+}
+
+impl Stl {
+    pub fn read<R: Read>(mut reader: R) -> Result<Stl, Box<dyn Error>> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        // ASCII files start with "solid", but so do the headers of some binary ones, which then
+        // have to hold as many facets as their count says. Text read as a count is far too many.
+        let binary_size = data.get(80..84).map(|count| {
+            84 + 50 * u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize
+        });
+        let fits = binary_size.is_some_and(|size| size <= data.len());
+        if data.starts_with(b"solid") && !fits {
+            let mesh = stl_io::read_stl(&mut Cursor::new(data))?;
+            let vertex = |id: usize| Vector3::from(mesh.vertices[id]);
+            let facets = mesh
+                .faces
+                .iter()
+                .map(|face| {
+                    [
+                        vertex(face.vertices[0]),
+                        vertex(face.vertices[1]),
+                        vertex(face.vertices[2]),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            return Ok(Stl {
+                colors: vec![None; facets.len()],
+                facets,
+                color: None,
+            });
+        }
+        let size = match binary_size {
+            None => return Err("stl: shorter than a header".into()),
+            Some(size) if size > data.len() => {
+                return Err(format!("stl: {} bytes long instead of {}", data.len(), size).into())
+            }
+            Some(size) => size, // Anything after the facets isn't part of them
+        };
+
+        // Materialise's header has "COLOR=" and the object's RGBA
+        let header = &data[..80];
+        let color = header
+            .windows(10)
+            .find(|window| window.starts_with(b"COLOR="))
+            .map(|window| (window[6], window[7], window[8]));
+        let mut stl = Stl {
+            color,
+            ..Stl::default()
+        };
+        for facet in data[84..size].chunks_exact(50) {
+            let float = |offset: usize| {
+                f32::from_le_bytes([
+                    facet[offset],
+                    facet[offset + 1],
+                    facet[offset + 2],
+                    facet[offset + 3],
+                ])
+            };
+            // The normal comes first, it's left to the renderer to work out
+            let vertex =
+                |offset: usize| Vector3::new(float(offset), float(offset + 4), float(offset + 8));
+            stl.facets.push([vertex(12), vertex(24), vertex(36)]);
+            let attribute = u16::from_le_bytes([facet[48], facet[49]]);
+            let valid = attribute & 0x8000 != 0;
+            stl.colors.push(match (color, valid) {
+                // Materialise clears the top bit for facets with their own color, red first
+                (Some(_), false) => Some((
+                    channel(attribute, 0),
+                    channel(attribute, 5),
+                    channel(attribute, 10),
+                )),
+                (Some(_), true) => None,
+                // VisCAM and SolidView set it for facets with a color, blue first
+                (None, true) => Some((
+                    channel(attribute, 10),
+                    channel(attribute, 5),
+                    channel(attribute, 0),
+                )),
+                (None, false) => None,
+            });
+        }
+        Ok(stl)
+    }
+
+    /// Colors facets without their own with the object's color, or else `default`
+    pub fn to_simple_mesh_with_color(&self, default: (u8, u8, u8)) -> SimpleMesh {
+        let mut triangles = Vec::with_capacity(self.facets.len());
+        for (facet, color) in self.facets.iter().zip(&self.colors) {
+            let triangle = Triangle {
+                color: color.or(self.color).unwrap_or(default),
+                v1: facet[0].insert_row(3, 1.0),
+                v2: facet[1].insert_row(3, 1.0),
+                v3: facet[2].insert_row(3, 1.0),
+                ..Triangle::default()
+            };
+            triangles.push(triangle);
+        }
+        SimpleMesh::new(triangles)
+    }
+}
+
+impl ToSimpleMesh for Stl {
+    fn to_simple_mesh(&self) -> SimpleMesh {
+        self.to_simple_mesh_with_color(STL_COLOR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector4;
+
+    fn binary(header: &[u8], attributes: &[u16]) -> Vec<u8> {
+        let mut data = header.to_vec();
+        data.resize(80, 0);
+        data.extend_from_slice(&(attributes.len() as u32).to_le_bytes());
+        for (i, attribute) in attributes.iter().enumerate() {
+            let vertices = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, i as f32];
+            data.extend_from_slice(&[0; 12]);
+            for value in vertices.iter() {
+                data.extend_from_slice(&f32::to_le_bytes(*value));
+            }
+            data.extend_from_slice(&attribute.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_viscam() {
+        // Red, then no color
+        let data = binary(b"solid but binary", &[0x8000 | 0x1F << 10, 0x1F]);
+        let stl = Stl::read(data.as_slice()).unwrap();
+        assert_eq!(stl.facets[1][2], Vector3::new(0.0, 1.0, 1.0));
+        assert_eq!(stl.colors, vec![Some((255, 0, 0)), None]);
+        let mesh = stl.to_simple_mesh_with_color((1, 2, 3));
+        assert_eq!(mesh.triangles[1].color, (1, 2, 3));
+        assert_eq!(mesh.bounding_box.max, Vector4::new(1.0, 1.0, 1.0, 1.0));
+        let mut trailing = data.clone();
+        trailing.extend_from_slice(b"padding");
+        assert_eq!(Stl::read(trailing.as_slice()).unwrap(), stl);
+    }
+
+    #[test]
+    fn test_materialise() {
+        // Blue of its own, then the object's
+        let data = binary(b"COLOR=\x10\x20\x30\xFF", &[0x1F << 10, 0x8000]);
+        let stl = Stl::read(data.as_slice()).unwrap();
+        assert_eq!(stl.color, Some((0x10, 0x20, 0x30)));
+        let mesh = stl.to_simple_mesh();
+        assert_eq!(mesh.triangles[0].color, (0, 0, 255));
+        assert_eq!(mesh.triangles[1].color, (0x10, 0x20, 0x30));
+        assert!(Stl::read(&data[..100]).is_err());
+        let mut trailing = data.clone();
+        trailing.extend_from_slice(&[0; 50]);
+        assert_eq!(Stl::read(trailing.as_slice()).unwrap().facets.len(), 2);
+    }
+
+    #[test]
+    fn test_ascii() {
+        let text = "solid triangle
+facet normal 0 0 1
+outer loop
+vertex 0 0 0
+vertex 1 0 0
+vertex 0 1 0
+endloop
+endfacet
+endsolid triangle
+";
+        let stl = Stl::read(text.as_bytes()).unwrap();
+        assert_eq!(stl.facets.len(), 1);
+        assert_eq!(stl.to_simple_mesh().triangles[0].color, STL_COLOR);
+    }
+}
@@ -0,0 +1,52 @@
+use nalgebra::Vector2;
+
+/// An RGB image mapped onto a mesh by its triangles' texture coordinates. (0, 0) is the top-left
+/// corner of the image and (1, 1) the bottom-right one, coordinates outside of it wrap around.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Texture {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<(u8, u8, u8)>, // Row by row, from the top
+}
+
+impl Texture {
+    /// The color of the pixel nearest to `uv`
+    pub fn sample(&self, uv: Vector2<f32>) -> (u8, u8, u8) {
+        if self.width == 0 || self.height == 0 {
+            return (255, 255, 255);
+        }
+        let wrap = |t: f32, size: usize| ((t.rem_euclid(1.0) * size as f32) as usize).min(size - 1);
+        self.pixels[wrap(uv.y, self.height) * self.width + wrap(uv.x, self.width)]
+    }
+}
+
+/// Multiplies two colors channel by channel, as if they were from 0 to 1
+pub fn modulate(a: (u8, u8, u8), b: (u8, u8, u8)) -> (u8, u8, u8) {
+    let channel = |a: u8, b: u8| ((a as u32 * b as u32 + 127) / 255) as u8;
+    (channel(a.0, b.0), channel(a.1, b.1), channel(a.2, b.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample() {
+        let texture = Texture {
+            width: 2,
+            height: 2,
+            pixels: vec![(255, 0, 0), (0, 255, 0), (0, 0, 255), (255, 255, 255)],
+        };
+        assert_eq!(texture.sample(Vector2::new(0.25, 0.25)), (255, 0, 0));
+        assert_eq!(texture.sample(Vector2::new(0.75, 0.25)), (0, 255, 0));
+        assert_eq!(texture.sample(Vector2::new(0.25, 0.75)), (0, 0, 255));
+        assert_eq!(texture.sample(Vector2::new(1.0, 1.0)), (255, 0, 0)); // Wraps around
+        assert_eq!(texture.sample(Vector2::new(-0.25, -0.25)), (255, 255, 255));
+    }
+
+    #[test]
+    fn test_modulate() {
+        assert_eq!(modulate((255, 128, 0), (255, 255, 255)), (255, 128, 0));
+        assert_eq!(modulate((255, 128, 0), (128, 128, 128)), (128, 64, 0));
+    }
+}
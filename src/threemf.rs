@@ -0,0 +1,370 @@
+use crate::geometry::{SimpleMesh, Triangle};
+use nalgebra::{Matrix4, Vector4};
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{Read, Seek};
+
+/// Components can nest, but not deeper than this, which also stops objects that contain themselves
+const MAX_DEPTH: usize = 32;
+/// Objects can be shared by any number of components, but a build doesn't use more components than
+/// this, or make more triangles, however many times they repeat
+const MAX_COMPONENTS: usize = 10_000;
+const MAX_TRIANGLES: usize = 10_000_000;
+
+/// The build of a 3MF file: one mesh for every build item, with its transform and its components'
+/// applied, and triangles colored by their base materials or color groups
+pub struct ThreeMf {
+    pub meshes: Vec<SimpleMesh>,
+}
+
+/// A `#RRGGBB` or `#RRGGBBAA` color, without its alpha
+fn hex(color: &str) -> Option<(u8, u8, u8)> {
+    let digits = color.strip_prefix('#')?;
+    if (digits.len() != 6 && digits.len() != 8) || !digits.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// A `transform` attribute's 3x4 matrix, whose columns are the rows of a 4x3 matrix that
+/// multiplies points as row vectors
+fn transform(node: Node) -> Result<Matrix4<f32>, Box<dyn Error>> {
+    let text = match node.attribute("transform") {
+        None => return Ok(Matrix4::identity()),
+        Some(text) => text,
+    };
+    let m = text
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<f32>, _>>()?;
+    if m.len() != 12 {
+        return Err(format!("3mf: transform with {} values instead of 12", m.len()).into());
+    }
+    #[rustfmt::skip]
+    let matrix = Matrix4::new(
+        m[0], m[3], m[6], m[9],
+        m[1], m[4], m[7], m[10],
+        m[2], m[5], m[8], m[11],
+        0.0, 0.0, 0.0, 1.0,
+    );
+    Ok(matrix)
+}
+
+fn attribute<T: std::str::FromStr>(node: Node, name: &str) -> Result<Option<T>, Box<dyn Error>> {
+    match node.attribute(name) {
+        None => Ok(None),
+        Some(value) => match value.parse() {
+            Ok(value) => Ok(Some(value)),
+            Err(_) => Err(format!("3mf: {} isn't a valid {}", value, name).into()),
+        },
+    }
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.tag_name().name() == name)
+}
+
+/// The resources build items are made of
+struct Resources<'a, 'input> {
+    objects: HashMap<u32, Node<'a, 'input>>,
+    colors: HashMap<u32, Vec<(u8, u8, u8)>>, // Base materials and color groups, by their id
+    components: usize,                       // Used so far, by every build item
+    triangles: usize,
+}
+
+impl Resources<'_, '_> {
+    /// Adds the triangles of an object and its components to `triangles`
+    fn add(
+        &mut self,
+        id: u32,
+        transform: &Matrix4<f32>,
+        depth: usize,
+        triangles: &mut Vec<Triangle>,
+    ) -> Result<(), Box<dyn Error>> {
+        if depth > MAX_DEPTH {
+            return Err(format!("3mf: components nest deeper than {}", MAX_DEPTH).into());
+        }
+        let object = *self
+            .objects
+            .get(&id)
+            .ok_or_else(|| format!("3mf: no object {}", id))?;
+        if let Some(components) = child(object, "components") {
+            for component in components.children().filter(|node| node.is_element()) {
+                self.components += 1;
+                if self.components > MAX_COMPONENTS {
+                    return Err(format!("3mf: more than {} components", MAX_COMPONENTS).into());
+                }
+                let id =
+                    attribute(component, "objectid")?.ok_or("3mf: component without objectid")?;
+                let transform = transform * self::transform(component)?;
+                self.add(id, &transform, depth + 1, triangles)?;
+            }
+        }
+        let mesh = match child(object, "mesh") {
+            None => return Ok(()),
+            Some(mesh) => mesh,
+        };
+        let mut vertices = vec![];
+        for vertex in child(mesh, "vertices")
+            .iter()
+            .flat_map(|node| node.children())
+        {
+            if vertex.is_element() {
+                let coordinate = |name| -> Result<f32, Box<dyn Error>> {
+                    Ok(attribute(vertex, name)?.ok_or("3mf: vertex without coordinate")?)
+                };
+                let position =
+                    Vector4::new(coordinate("x")?, coordinate("y")?, coordinate("z")?, 1.0);
+                vertices.push(transform * position);
+            }
+        }
+        // Triangles without properties of their own take the object's
+        let object_group: Option<u32> = attribute(object, "pid")?;
+        let object_index: Option<usize> = attribute(object, "pindex")?;
+        for node in child(mesh, "triangles")
+            .iter()
+            .flat_map(|node| node.children())
+        {
+            if !node.is_element() {
+                continue;
+            }
+            self.triangles += 1;
+            if self.triangles > MAX_TRIANGLES {
+                return Err(format!("3mf: more than {} triangles", MAX_TRIANGLES).into());
+            }
+            let mut corners = [0; 3];
+            for (corner, name) in corners.iter_mut().zip(&["v1", "v2", "v3"]) {
+                *corner = attribute(node, name)?.ok_or("3mf: triangle without vertex")?;
+                if *corner >= vertices.len() {
+                    return Err(format!(
+                        "3mf: triangle uses vertex {} of {}",
+                        corner,
+                        vertices.len()
+                    )
+                    .into());
+                }
+            }
+            let mut triangle = Triangle {
+                color: (0xFF, 0xFF, 0xFF),
+                v1: vertices[corners[0]],
+                v2: vertices[corners[1]],
+                v3: vertices[corners[2]],
+                ..Triangle::default()
+            };
+            let group = attribute(node, "pid")?
+                .or(object_group)
+                .and_then(|id| self.colors.get(&id));
+            let p1 = attribute(node, "p1")?.or(object_index);
+            if let (Some(group), Some(p1)) = (group, p1) {
+                let color = |index: usize| group.get(index).copied().unwrap_or((0xFF, 0xFF, 0xFF));
+                let p2 = attribute(node, "p2")?.unwrap_or(p1);
+                let p3 = attribute(node, "p3")?.unwrap_or(p1);
+                triangle.color = color(p1);
+                if p2 != p1 || p3 != p1 {
+                    triangle.colors = Some([color(p1), color(p2), color(p3)]);
+                }
+            }
+            triangles.push(triangle);
+        }
+        Ok(())
+    }
+}
+
+impl ThreeMf {
+    pub fn read<R: Read + Seek>(reader: R) -> Result<ThreeMf, Box<dyn Error>> {
+        let mut archive = zip::ZipArchive::new(reader)?;
+        // The package relationships point to the model, which is usually at 3D/3dmodel.model
+        let mut path = String::from("3D/3dmodel.model");
+        if let Ok(mut file) = archive.by_name("_rels/.rels") {
+            let mut text = String::new();
+            file.read_to_string(&mut text)?;
+            let relationships = Document::parse(&text)?;
+            let model = relationships.descendants().find(|node| {
+                node.attribute("Type")
+                    .is_some_and(|kind| kind.ends_with("/3dmodel"))
+            });
+            if let Some(target) = model.and_then(|node| node.attribute("Target")) {
+                path = target.trim_start_matches('/').to_string();
+            }
+        }
+        let mut text = String::new();
+        archive
+            .by_name(&path)
+            .map_err(|_| format!("3mf: no model at {}", path))?
+            .read_to_string(&mut text)?;
+        ThreeMf::from_model(&text)
+    }
+
+    /// Reads the model XML inside of a 3MF package
+    pub fn from_model(text: &str) -> Result<ThreeMf, Box<dyn Error>> {
+        let document = Document::parse(text)?;
+        let model = document.root_element();
+        let mut resources = Resources {
+            objects: HashMap::new(),
+            colors: HashMap::new(),
+            components: 0,
+            triangles: 0,
+        };
+        let nodes = child(model, "resources").ok_or("3mf: no resources")?;
+        for node in nodes.children().filter(|node| node.is_element()) {
+            let id = match attribute(node, "id")? {
+                None => continue,
+                Some(id) => id,
+            };
+            let (element, color) = match node.tag_name().name() {
+                "object" => {
+                    resources.objects.insert(id, node);
+                    continue;
+                }
+                "basematerials" => ("base", "displaycolor"),
+                "colorgroup" => ("color", "color"),
+                _ => continue,
+            };
+            let colors = node
+                .children()
+                .filter(|child| child.tag_name().name() == element)
+                .map(|child| child.attribute(color).and_then(hex))
+                .map(|color| color.unwrap_or((0xFF, 0xFF, 0xFF)))
+                .collect();
+            resources.colors.insert(id, colors);
+        }
+
+        let mut meshes = vec![];
+        let build = child(model, "build").ok_or("3mf: no build")?;
+        for item in build
+            .children()
+            .filter(|node| node.tag_name().name() == "item")
+        {
+            let id = attribute(item, "objectid")?.ok_or("3mf: item without objectid")?;
+            let mut triangles = vec![];
+            resources.add(id, &transform(item)?, 0, &mut triangles)?;
+            meshes.push(SimpleMesh::new(triangles));
+        }
+        Ok(ThreeMf { meshes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    const MODEL: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<model unit="millimeter" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">
+  <resources>
+    <basematerials id="1">
+      <base name="Red" displaycolor="#FF0000" />
+      <base name="Green" displaycolor="#00FF0080" />
+    </basematerials>
+    <object id="2" type="model" pid="1" pindex="0">
+      <mesh>
+        <vertices>
+          <vertex x="0" y="0" z="0" />
+          <vertex x="1" y="0" z="0" />
+          <vertex x="0" y="1" z="0" />
+          <vertex x="0" y="0" z="1" />
+        </vertices>
+        <triangles>
+          <triangle v1="0" v2="2" v3="1" />
+          <triangle v1="0" v2="1" v3="3" p1="1" />
+          <triangle v1="0" v2="3" v3="2" p1="0" p2="1" />
+        </triangles>
+      </mesh>
+    </object>
+    <object id="3" type="model">
+      <components>
+        <component objectid="2" transform="1 0 0 0 1 0 0 0 1 10 0 0" />
+      </components>
+    </object>
+  </resources>
+  <build>
+    <item objectid="3" transform="2 0 0 0 2 0 0 0 2 0 0 5" />
+  </build>
+</model>"##;
+
+    #[test]
+    fn test_model() {
+        let model = ThreeMf::from_model(MODEL).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.triangles.len(), 3);
+        // Moved by the component, then scaled and moved by the item
+        assert_eq!(mesh.triangles[0].v3, Vector4::new(22.0, 0.0, 5.0, 1.0));
+        assert_eq!(mesh.bounding_box.min, Vector4::new(20.0, 0.0, 5.0, 1.0));
+        assert_eq!(mesh.bounding_box.max, Vector4::new(22.0, 2.0, 7.0, 1.0));
+        assert_eq!(mesh.triangles[0].color, (255, 0, 0));
+        assert_eq!(mesh.triangles[1].color, (0, 255, 0));
+        assert_eq!(mesh.triangles[0].colors, None);
+        assert_eq!(
+            mesh.triangles[2].colors,
+            Some([(255, 0, 0), (0, 255, 0), (255, 0, 0)])
+        );
+    }
+
+    #[test]
+    fn test_package() {
+        let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+        let options = zip::write::FileOptions::default();
+        zip.start_file("_rels/.rels", options).unwrap();
+        zip.write_all(
+            br#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Target="/3D/model.model" Id="rel0" Type="http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel" />
+</Relationships>"#,
+        )
+        .unwrap();
+        zip.start_file("3D/model.model", options).unwrap();
+        zip.write_all(MODEL.as_bytes()).unwrap();
+        let data = zip.finish().unwrap().into_inner();
+
+        let model = ThreeMf::read(Cursor::new(data)).unwrap();
+        assert_eq!(model.meshes[0].triangles.len(), 3);
+        assert!(ThreeMf::read(Cursor::new(b"not a zip".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_recursion() {
+        let model = r#"<model><resources><object id="1"><components>
+<component objectid="1" /></components></object></resources>
+<build><item objectid="1" /></build></model>"#;
+        assert!(ThreeMf::from_model(model).is_err());
+    }
+
+    #[test]
+    fn test_shared() {
+        // Object 2 twice, side by side
+        let twice = r#"<object id="4"><components><component objectid="2" />
+<component objectid="2" transform="1 0 0 0 1 0 0 0 1 5 0 0" /></components></object>
+</resources>"#;
+        let model = ThreeMf::from_model(
+            &MODEL
+                .replace("</resources>", twice)
+                .replace(r#"<item objectid="3""#, r#"<item objectid="4""#),
+        )
+        .unwrap();
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.triangles.len(), 6);
+        assert_eq!(mesh.bounding_box.max.x, 2.0 * 6.0);
+
+        // Every object ten times over the one before, a million copies of one triangle
+        let mut objects = String::from(
+            r#"<object id="1"><mesh><vertices><vertex x="0" y="0" z="0" /></vertices>
+<triangles><triangle v1="0" v2="0" v3="0" /></triangles></mesh></object>"#,
+        );
+        for id in 2..=7 {
+            let component = format!(r#"<component objectid="{}" />"#, id - 1);
+            objects += &format!(
+                r#"<object id="{}"><components>{}</components></object>"#,
+                id,
+                component.repeat(10)
+            );
+        }
+        let model = format!(
+            r#"<model><resources>{}</resources><build><item objectid="7" /></build></model>"#,
+            objects
+        );
+        assert!(ThreeMf::from_model(&model).is_err());
+    }
+}